@@ -0,0 +1,203 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use rodio::source::ChannelVolume;
+use rodio::Decoder;
+
+use crate::audio::music::{invalid_data, open_file};
+use crate::audio::sounds::Sound;
+use crate::audio::{balance, AudioManager, Bus, VoiceId};
+use crate::diagnostics::{log_info, log_warn};
+use crate::random::Rng;
+
+/// How far ambient one-shots are panned at most.
+const MAX_AMBIENCE_PAN: f32 = 0.8;
+
+/// A sound file looped for as long as the map is played, such as wind.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AmbienceBed {
+    pub path: PathBuf,
+    pub volume: f32,
+}
+
+/// A sound played over and over at random intervals and random positions, such as a bird.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AmbienceSound {
+    /// A sound file or a synthesizer preset.
+    pub path: PathBuf,
+    pub min_interval: Duration,
+    pub max_interval: Duration,
+    pub volume: f32,
+}
+
+impl AmbienceSound {
+    fn random_interval(&self, rng: &mut Rng) -> Duration {
+        Duration::from_secs_f32(rng.range(self.min_interval.as_secs_f32(), self.max_interval.as_secs_f32()))
+    }
+}
+
+/// The environmental sounds of a map, which keep it alive while the player stands still.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Ambience {
+    pub beds: Vec<AmbienceBed>,
+    pub sounds: Vec<AmbienceSound>,
+}
+
+struct ScheduledSound {
+    sound: Sound,
+    config: AmbienceSound,
+    next_at: Instant,
+}
+
+/// The ambience side of the `AudioManager`: the playing beds and the one-shots waiting for
+/// their next turn.
+#[derive(Default)]
+pub(super) struct AmbienceState {
+    current: Ambience,
+    beds: Vec<VoiceId>,
+    scheduled: Vec<ScheduledSound>,
+}
+
+impl AmbienceState {
+    pub(super) fn is_bed(&self, voice: VoiceId) -> bool {
+        self.beds.contains(&voice)
+    }
+}
+
+impl AudioManager {
+    /// Switches to the given ambience, stopping the sounds of the previous one. Passing the
+    /// ambience which is already playing does nothing.
+    pub fn play_ambience(&mut self, ambience: &Ambience) {
+        if self.ambience.current == *ambience {
+            return;
+        }
+        self.ambience.current = ambience.clone();
+
+        for voice in std::mem::take(&mut self.ambience.beds) {
+            self.stop(voice);
+        }
+        self.ambience.scheduled.clear();
+
+        for bed in &ambience.beds {
+            let source = open_file(&bed.path).and_then(|file| Decoder::new_looped(file).map_err(invalid_data));
+            match source {
+                Ok(source) => {
+                    if let Some(voice) = self.play_with_volume(Bus::Ambience, source, bed.volume) {
+                        log_info!(Audio, "Playing ambience {}", bed.path.display());
+                        self.ambience.beds.push(voice);
+                    }
+                }
+                Err(error) => log_warn!(Audio, "Failed to play ambience {}: {}", bed.path.display(), error),
+            }
+        }
+
+        let now = Instant::now();
+        for config in &ambience.sounds {
+            match Sound::load(&config.path) {
+                Ok(sound) => {
+                    let next_at = now + config.random_interval(&mut self.rng);
+                    self.ambience.scheduled.push(ScheduledSound { sound, config: config.clone(), next_at });
+                }
+                Err(error) => log_warn!(Audio, "Failed to load ambience sound {}: {}", config.path.display(), error),
+            }
+        }
+    }
+
+    /// Plays the one-shots whose time has come, each panned to a random side.
+    pub(super) fn update_ambience(&mut self) {
+        let now = Instant::now();
+        for index in 0..self.ambience.scheduled.len() {
+            let scheduled = &mut self.ambience.scheduled[index];
+            if scheduled.next_at > now {
+                continue;
+            }
+
+            scheduled.next_at = now + scheduled.config.random_interval(&mut self.rng);
+            let pan = self.rng.range(-MAX_AMBIENCE_PAN, MAX_AMBIENCE_PAN);
+            let source = ChannelVolume::new(scheduled.sound.source(&mut self.rng), balance(pan).to_vec());
+            let volume = scheduled.config.volume;
+            self.play_with_volume(Bus::Ambience, source, volume);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::tests::held_audio;
+
+    fn bed(path: &str) -> AmbienceBed {
+        AmbienceBed { path: PathBuf::from(path), volume: 0.5 }
+    }
+
+    fn bird(min: f32, max: f32) -> AmbienceSound {
+        AmbienceSound {
+            path: PathBuf::from("assets/sounds/bird.sfx"),
+            min_interval: Duration::from_secs_f32(min),
+            max_interval: Duration::from_secs_f32(max),
+            volume: 0.6,
+        }
+    }
+
+    #[test]
+    fn changing_the_ambience_replaces_its_beds() {
+        let (mut audio, voices) = held_audio();
+        let wind = Ambience { beds: vec![bed("assets/sounds/walk_1.wav")], sounds: Vec::new() };
+        let rain = Ambience { beds: vec![bed("assets/sounds/walk_2.wav")], sounds: Vec::new() };
+
+        audio.play_ambience(&wind);
+        assert_eq!(voices.lock().unwrap().len(), 1);
+        assert_eq!(voices.lock().unwrap()[0].bus, Bus::Ambience);
+
+        // The same ambience again keeps its bed playing
+        audio.play_ambience(&wind);
+        assert_eq!(voices.lock().unwrap().len(), 1);
+        assert!(!voices.lock().unwrap()[0].stopped);
+
+        audio.play_ambience(&rain);
+        let voices = voices.lock().unwrap();
+        assert_eq!(voices.len(), 2);
+        assert!(voices[0].stopped);
+        assert!(!voices[1].stopped);
+    }
+
+    #[test]
+    fn one_shots_fire_within_their_interval() {
+        let (mut audio, voices) = held_audio();
+        let before = Instant::now();
+        audio.play_ambience(&Ambience { beds: Vec::new(), sounds: vec![bird(2.0, 3.0)] });
+        let after = Instant::now();
+
+        let next_at = audio.ambience.scheduled[0].next_at;
+        assert!(next_at >= before + Duration::from_secs(2) && next_at <= after + Duration::from_secs(3));
+
+        // Not due yet
+        audio.update_ambience();
+        assert!(voices.lock().unwrap().is_empty());
+
+        let before = Instant::now();
+        audio.ambience.scheduled[0].next_at = before;
+        audio.update_ambience();
+        let after = Instant::now();
+        assert_eq!(voices.lock().unwrap().len(), 1);
+
+        // Played and scheduled again
+        let next_at = audio.ambience.scheduled[0].next_at;
+        assert!(next_at >= before + Duration::from_secs(2) && next_at <= after + Duration::from_secs(3));
+    }
+
+    #[test]
+    fn a_burst_of_one_shots_never_cuts_a_bed() {
+        let (mut audio, voices) = held_audio();
+        audio.play_ambience(&Ambience { beds: vec![bed("assets/sounds/walk_1.wav")], sounds: vec![bird(0.0, 0.0)] });
+
+        for _ in 0..3 * Bus::Ambience.voice_limit() {
+            audio.update_ambience();
+        }
+
+        let voices = voices.lock().unwrap();
+        assert_eq!(voices.len(), 1 + 3 * Bus::Ambience.voice_limit());
+        assert!(!voices[0].stopped);
+        assert_eq!(voices.iter().filter(|voice| !voice.stopped).count(), Bus::Ambience.voice_limit());
+    }
+}
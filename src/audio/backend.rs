@@ -0,0 +1,92 @@
+use rodio::{OutputStream, OutputStreamHandle, Sink, Source, StreamError};
+
+use crate::audio::sounds::SoundId;
+use crate::audio::Bus;
+use crate::diagnostics::log_warn;
+
+/// A source as handed to a backend, converted to `f32` samples.
+pub type BoxedSource = Box<dyn Source<Item = f32> + Send>;
+
+/// Where the `AudioManager`'s voices end up: a sound device, nowhere, or a recording.
+pub trait AudioBackend {
+    /// Starts playing a source on a new voice. Returns `None` if the voice can't be created.
+    fn play(&mut self, bus: Bus, source: BoxedSource, volume: f32) -> Option<Box<dyn BackendVoice>>;
+
+    /// Called for every sound effect played by id, with the volume it plays at.
+    fn sound_played(&mut self, _bus: Bus, _id: SoundId, _volume: f32) {}
+
+    /// Called once per frame.
+    fn update(&mut self) {}
+}
+
+/// A voice created by an `AudioBackend`.
+pub trait BackendVoice {
+    fn set_volume(&self, volume: f32);
+    fn stop(&self);
+    fn is_finished(&self) -> bool;
+}
+
+/// Plays through the default audio output device.
+pub struct RodioBackend {
+    // The stream has to stay alive for as long as anything plays
+    _stream: OutputStream,
+    handle: OutputStreamHandle,
+}
+
+impl RodioBackend {
+    pub fn open() -> Result<Self, StreamError> {
+        let (stream, handle) = OutputStream::try_default()?;
+        Ok(Self { _stream: stream, handle })
+    }
+}
+
+impl AudioBackend for RodioBackend {
+    fn play(&mut self, bus: Bus, source: BoxedSource, volume: f32) -> Option<Box<dyn BackendVoice>> {
+        let sink = match Sink::try_new(&self.handle) {
+            Ok(sink) => sink,
+            Err(error) => {
+                log_warn!(Audio, "Failed to create a voice on {:?}: {}", bus, error);
+                return None;
+            }
+        };
+        sink.set_volume(volume);
+        sink.append(source);
+        Some(Box::new(sink))
+    }
+}
+
+impl BackendVoice for Sink {
+    fn set_volume(&self, volume: f32) {
+        Sink::set_volume(self, volume);
+    }
+
+    fn stop(&self) {
+        Sink::stop(self);
+    }
+
+    fn is_finished(&self) -> bool {
+        self.empty()
+    }
+}
+
+/// A voice which finishes as soon as it starts, for backends which play nothing.
+pub(super) struct SilentVoice;
+
+impl BackendVoice for SilentVoice {
+    fn set_volume(&self, _volume: f32) {}
+
+    fn stop(&self) {}
+
+    fn is_finished(&self) -> bool {
+        true
+    }
+}
+
+/// Plays nothing, for machines without a sound device and headless runs.
+pub struct NullBackend;
+
+impl AudioBackend for NullBackend {
+    fn play(&mut self, _bus: Bus, _source: BoxedSource, _volume: f32) -> Option<Box<dyn BackendVoice>> {
+        Some(Box::new(SilentVoice))
+    }
+}
@@ -0,0 +1,72 @@
+//! A backend which records the sound effects gameplay plays, so tests can check them.
+
+use std::sync::{Arc, Mutex};
+
+use crate::audio::backend::{AudioBackend, BackendVoice, BoxedSource, SilentVoice};
+use crate::audio::sounds::SoundId;
+use crate::audio::Bus;
+
+/// A sound effect recorded by the `CaptureBackend`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CapturedSound {
+    pub id: SoundId,
+    pub bus: Bus,
+    /// Frames since the backend was created, counted by `AudioManager::update`.
+    pub frame: u64,
+    /// Volume of the voice relative to its bus, after distance attenuation.
+    pub volume: f32,
+}
+
+/// Sound effects recorded by a `CaptureBackend`, readable after the backend is handed to the
+/// `AudioManager`.
+#[derive(Debug, Clone, Default)]
+pub struct CaptureLog {
+    sounds: Arc<Mutex<Vec<CapturedSound>>>,
+}
+
+impl CaptureLog {
+    /// All sounds recorded so far, oldest first.
+    pub fn sounds(&self) -> Vec<CapturedSound> {
+        self.sounds.lock().unwrap().clone()
+    }
+
+    pub fn contains(&self, id: SoundId) -> bool {
+        self.sounds.lock().unwrap().iter().any(|sound| sound.id == id)
+    }
+
+    pub fn clear(&self) {
+        self.sounds.lock().unwrap().clear();
+    }
+}
+
+/// Plays nothing, but records every sound effect which would have been played, so tests can
+/// check which sounds gameplay produces.
+#[derive(Default)]
+pub struct CaptureBackend {
+    log: CaptureLog,
+    frame: u64,
+}
+
+impl CaptureBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn log(&self) -> CaptureLog {
+        self.log.clone()
+    }
+}
+
+impl AudioBackend for CaptureBackend {
+    fn play(&mut self, _bus: Bus, _source: BoxedSource, _volume: f32) -> Option<Box<dyn BackendVoice>> {
+        Some(Box::new(SilentVoice))
+    }
+
+    fn sound_played(&mut self, bus: Bus, id: SoundId, volume: f32) {
+        self.log.sounds.lock().unwrap().push(CapturedSound { id, bus, frame: self.frame, volume });
+    }
+
+    fn update(&mut self) {
+        self.frame += 1;
+    }
+}
@@ -0,0 +1,445 @@
+use std::time::Instant;
+
+use rodio::cpal::FromSample;
+use rodio::source::ChannelVolume;
+use rodio::{Sample, Source};
+
+use crate::audio::backend::{AudioBackend, BackendVoice};
+use crate::audio::music::MusicState;
+use crate::audio::ambience::AmbienceState;
+use crate::audio::sounds::{PlayParams, SoundBank, SoundId};
+use crate::diagnostics::{log_debug, log_warn};
+use crate::random::Rng;
+
+pub mod ambience;
+pub mod backend;
+#[cfg(test)]
+pub mod capture;
+pub mod sounds;
+pub mod synth;
+pub mod music;
+pub mod tracker;
+
+/// A group of voices which share a volume and a voice limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Bus {
+    /// Short gameplay sounds such as jumps, kicks and explosions.
+    Sfx,
+    /// Background music.
+    Music,
+    /// Looping environmental sounds.
+    Ambience,
+    /// Menu and interface feedback.
+    Ui,
+}
+
+impl Bus {
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Number of voices the bus can play at once before the oldest one is cut off.
+    fn voice_limit(self) -> usize {
+        match self {
+            Bus::Sfx => 16,
+            Bus::Music => 2,
+            Bus::Ambience => 6,
+            Bus::Ui => 4,
+        }
+    }
+}
+
+/// Distance from the listener at which a sound is panned fully to one side.
+const PAN_DISTANCE: f32 = 128.0;
+/// How far sounds are panned at most, where 1.0 would silence the opposite speaker.
+const MAX_PAN: f32 = 0.7;
+/// Distance from the listener over which sounds fade down to `MIN_DISTANCE_VOLUME`.
+const HEARING_DISTANCE: f32 = 320.0;
+const MIN_DISTANCE_VOLUME: f32 = 0.35;
+
+/// Where positioned sounds are heard from, usually the player.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Listener {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Listener {
+    /// Returns the volume and the left and right gains of a sound at the given position.
+    fn spatialize(&self, (x, y): (f32, f32)) -> (f32, [f32; 2]) {
+        let distance = (x - self.x).hypot(y - self.y);
+        let volume = (1.0 - distance / HEARING_DISTANCE).max(MIN_DISTANCE_VOLUME);
+
+        let pan = ((x - self.x) / PAN_DISTANCE).clamp(-1.0, 1.0) * MAX_PAN;
+        (volume, balance(pan))
+    }
+}
+
+/// Left and right gains for a pan from -1.0 (left) to 1.0 (right). Balance panning keeps
+/// centred sounds at full volume in both speakers.
+fn balance(pan: f32) -> [f32; 2] {
+    [(1.0 - pan).min(1.0), (1.0 + pan).min(1.0)]
+}
+
+/// Identifies a playing voice, e.g. to stop a looping sound later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VoiceId(u64);
+
+struct Voice {
+    id: VoiceId,
+    bus: Bus,
+    output: Box<dyn BackendVoice>,
+    volume: f32,
+    started: Instant,
+}
+
+/// Mixes any number of overlapping sounds into the output of an `AudioBackend`.
+///
+/// Every sound plays on its own voice, so sounds overlap instead of queuing behind each
+/// other, and finished voices are cleaned up by `update`. Each voice belongs to a `Bus`,
+/// whose volume scales all of its voices and whose voice limit bounds how many can play at
+/// once: playing one more steals the oldest voice on the bus.
+pub struct AudioManager {
+    backend: Box<dyn AudioBackend>,
+    sounds: SoundBank,
+    listener: Listener,
+    // Varies synthesized sounds on every play
+    rng: Rng,
+    music: MusicState,
+    ambience: AmbienceState,
+    voices: Vec<Voice>,
+    bus_volumes: [f32; 4],
+    master_volume: f32,
+    next_voice_id: u64,
+}
+
+impl AudioManager {
+    /// Plays the given sounds and any other source through the backend.
+    pub fn new(sounds: SoundBank, backend: Box<dyn AudioBackend>) -> Self {
+        Self {
+            backend,
+            sounds,
+            listener: Listener::default(),
+            rng: Rng::from_time(),
+            music: MusicState::default(),
+            ambience: AmbienceState::default(),
+            voices: Vec::new(),
+            bus_volumes: [1.0; 4],
+            master_volume: 1.0,
+            next_voice_id: 0,
+        }
+    }
+
+    /// Plays one of the preloaded sounds on a new voice of the given bus.
+    pub fn play_sound(&mut self, bus: Bus, id: SoundId, params: PlayParams) -> Option<VoiceId> {
+        let Some(sound) = self.sounds.get(id) else {
+            log_warn!(Audio, "Can't play sound {}, it isn't loaded", id.name());
+            return None;
+        };
+
+        let source = sound.source(&mut self.rng).speed(params.pitch);
+        if id.ducks_music() {
+            self.duck_music();
+        }
+
+        let (volume, gains) = match params.position {
+            Some(position) => {
+                let (volume, gains) = self.listener.spatialize(position);
+                (params.volume * volume, Some(gains))
+            }
+            None => (params.volume, None),
+        };
+
+        self.backend.sound_played(bus, id, volume);
+        match gains {
+            Some(gains) => self.play_with_volume(bus, ChannelVolume::new(source, gains.to_vec()), volume),
+            None => self.play_with_volume(bus, source, volume),
+        }
+    }
+
+    /// Moves the listener positioned sounds are panned and attenuated relative to.
+    pub fn set_listener(&mut self, listener: Listener) {
+        self.listener = listener;
+    }
+
+    /// Plays a source on a new voice of the given bus. The voice's `volume` is scaled by the
+    /// bus and master volumes.
+    pub fn play_with_volume<S>(&mut self, bus: Bus, source: S, volume: f32) -> Option<VoiceId>
+    where
+        S: Source + Send + 'static,
+        S::Item: Sample + Send,
+        f32: FromSample<S::Item>,
+    {
+        self.release_finished_voices();
+
+        // Steal the oldest voice if the bus is full. Ambience beds are never stolen, since they
+        // are only started again when the map changes.
+        if self.voices.iter().filter(|voice| voice.bus == bus).count() >= bus.voice_limit() {
+            let oldest = self.voices.iter().enumerate()
+                .filter(|(_, voice)| voice.bus == bus && !self.ambience.is_bed(voice.id))
+                .min_by_key(|(_, voice)| voice.started)
+                .map(|(index, _)| index);
+            match oldest {
+                Some(oldest) => {
+                    log_debug!(Audio, "Voice limit of {:?} reached, stopping voice {:?}", bus, self.voices[oldest].id);
+                    self.voices.remove(oldest).output.stop();
+                }
+                None => {
+                    log_debug!(Audio, "Voice limit of {:?} reached by ambience beds, skipping sound", bus);
+                    return None;
+                }
+            }
+        }
+
+        let output = self.backend.play(bus, Box::new(source.convert_samples()), volume * self.effective_bus_volume(bus))?;
+
+        let id = VoiceId(self.next_voice_id);
+        self.next_voice_id += 1;
+        self.voices.push(Voice { id, bus, output, volume, started: Instant::now() });
+        Some(id)
+    }
+
+    /// Advances the music and ambience and releases voices which finished playing. Called once
+    /// per frame.
+    pub fn update(&mut self) {
+        self.update_music();
+        self.update_ambience();
+        self.release_finished_voices();
+        self.backend.update();
+    }
+
+    fn release_finished_voices(&mut self) {
+        self.voices.retain(|voice| !voice.output.is_finished());
+    }
+
+    pub fn stop(&mut self, id: VoiceId) {
+        if let Some(index) = self.voices.iter().position(|voice| voice.id == id) {
+            self.voices.remove(index).output.stop();
+        }
+    }
+
+    /// Stops every voice playing on the bus.
+    pub fn stop_bus(&mut self, bus: Bus) {
+        self.voices.retain(|voice| {
+            if voice.bus == bus {
+                voice.output.stop();
+            }
+            voice.bus != bus
+        });
+    }
+
+    /// Changes the volume of a single voice, relative to its bus.
+    pub fn set_voice_volume(&mut self, id: VoiceId, volume: f32) {
+        if let Some(index) = self.voices.iter().position(|voice| voice.id == id) {
+            let bus_volume = self.effective_bus_volume(self.voices[index].bus);
+            let voice = &mut self.voices[index];
+            voice.volume = volume;
+            voice.output.set_volume(volume * bus_volume);
+        }
+    }
+
+    pub fn bus_volume(&self, bus: Bus) -> f32 {
+        self.bus_volumes[bus.index()]
+    }
+
+    pub fn set_bus_volume(&mut self, bus: Bus, volume: f32) {
+        self.bus_volumes[bus.index()] = volume.max(0.0);
+        self.apply_volumes();
+    }
+
+    pub fn master_volume(&self) -> f32 {
+        self.master_volume
+    }
+
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.master_volume = volume.max(0.0);
+        self.apply_volumes();
+    }
+
+    fn effective_bus_volume(&self, bus: Bus) -> f32 {
+        self.bus_volumes[bus.index()] * self.master_volume
+    }
+
+    fn apply_volumes(&self) {
+        for voice in &self.voices {
+            voice.output.set_volume(voice.volume * self.effective_bus_volume(voice.bus));
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use rodio::source::SineWave;
+
+    use super::*;
+    use crate::audio::backend::BoxedSource;
+    use crate::audio::capture::CaptureBackend;
+
+    /// A voice of the `HeldBackend`, as seen by the test.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub(crate) struct HeldVoice {
+        pub bus: Bus,
+        pub volume: f32,
+        pub stopped: bool,
+    }
+
+    /// Plays nothing, but keeps every voice alive until it is stopped and records its volume.
+    #[derive(Default)]
+    pub(crate) struct HeldBackend {
+        pub voices: Arc<Mutex<Vec<HeldVoice>>>,
+    }
+
+    struct HeldOutput {
+        voices: Arc<Mutex<Vec<HeldVoice>>>,
+        index: usize,
+    }
+
+    impl AudioBackend for HeldBackend {
+        fn play(&mut self, bus: Bus, _source: BoxedSource, volume: f32) -> Option<Box<dyn BackendVoice>> {
+            let mut voices = self.voices.lock().unwrap();
+            voices.push(HeldVoice { bus, volume, stopped: false });
+            Some(Box::new(HeldOutput { voices: self.voices.clone(), index: voices.len() - 1 }))
+        }
+    }
+
+    impl BackendVoice for HeldOutput {
+        fn set_volume(&self, volume: f32) {
+            self.voices.lock().unwrap()[self.index].volume = volume;
+        }
+
+        fn stop(&self) {
+            self.voices.lock().unwrap()[self.index].stopped = true;
+        }
+
+        fn is_finished(&self) -> bool {
+            self.voices.lock().unwrap()[self.index].stopped
+        }
+    }
+
+    /// Returns an audio manager without sounds on a `HeldBackend`, and the backend's voices.
+    pub(crate) fn held_audio() -> (AudioManager, Arc<Mutex<Vec<HeldVoice>>>) {
+        let backend = HeldBackend::default();
+        let voices = backend.voices.clone();
+        (AudioManager::new(SoundBank::default(), Box::new(backend)), voices)
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn full_bus_steals_its_oldest_voice() {
+        let (mut audio, voices) = held_audio();
+        let first = audio.play_with_volume(Bus::Sfx, SineWave::new(440.0), 1.0).unwrap();
+        for _ in 1..Bus::Sfx.voice_limit() {
+            audio.play_with_volume(Bus::Sfx, SineWave::new(440.0), 1.0);
+        }
+        assert!(!voices.lock().unwrap()[0].stopped);
+
+        let newest = audio.play_with_volume(Bus::Sfx, SineWave::new(440.0), 1.0).unwrap();
+
+        let voices = voices.lock().unwrap();
+        assert!(voices[0].stopped);
+        assert!(voices[1..].iter().all(|voice| !voice.stopped));
+        assert_eq!(audio.voices.len(), Bus::Sfx.voice_limit());
+        assert!(audio.voices.iter().all(|voice| voice.id != first));
+        assert!(audio.voices.iter().any(|voice| voice.id == newest));
+    }
+
+    #[test]
+    fn voice_limit_is_per_bus() {
+        let (mut audio, voices) = held_audio();
+        for _ in 0..Bus::Music.voice_limit() {
+            audio.play_with_volume(Bus::Music, SineWave::new(440.0), 1.0);
+        }
+        audio.play_with_volume(Bus::Sfx, SineWave::new(440.0), 1.0);
+        assert!(voices.lock().unwrap().iter().all(|voice| !voice.stopped));
+
+        audio.play_with_volume(Bus::Music, SineWave::new(440.0), 1.0);
+        let voices = voices.lock().unwrap();
+        assert!(voices[0].stopped && voices[0].bus == Bus::Music);
+        assert!(voices.iter().filter(|voice| voice.stopped).count() == 1);
+    }
+
+    #[test]
+    fn voice_volume_is_scaled_by_bus_and_master_volume() {
+        let (mut audio, voices) = held_audio();
+        audio.set_bus_volume(Bus::Music, 0.5);
+        audio.set_master_volume(0.8);
+        let music = audio.play_with_volume(Bus::Music, SineWave::new(440.0), 0.5).unwrap();
+        audio.play_with_volume(Bus::Sfx, SineWave::new(440.0), 1.0);
+        assert_close(voices.lock().unwrap()[0].volume, 0.2);
+        assert_close(voices.lock().unwrap()[1].volume, 0.8);
+
+        audio.set_voice_volume(music, 1.0);
+        assert_close(voices.lock().unwrap()[0].volume, 0.4);
+
+        audio.set_master_volume(1.0);
+        assert_close(voices.lock().unwrap()[0].volume, 0.5);
+        assert_close(voices.lock().unwrap()[1].volume, 1.0);
+    }
+
+    #[test]
+    fn stop_bus_only_stops_that_bus() {
+        let (mut audio, voices) = held_audio();
+        audio.play_with_volume(Bus::Sfx, SineWave::new(440.0), 1.0);
+        audio.play_with_volume(Bus::Ambience, SineWave::new(440.0), 1.0);
+        audio.stop_bus(Bus::Sfx);
+
+        let voices = voices.lock().unwrap();
+        assert!(voices[0].stopped);
+        assert!(!voices[1].stopped);
+        assert_eq!(audio.voices.len(), 1);
+    }
+
+    #[test]
+    fn balance_keeps_centred_sounds_at_full_volume() {
+        assert_eq!(balance(0.0), [1.0, 1.0]);
+        assert_eq!(balance(1.0), [0.0, 1.0]);
+        assert_eq!(balance(-1.0), [1.0, 0.0]);
+        let [left, right] = balance(0.5);
+        assert_close(left, 0.5);
+        assert_close(right, 1.0);
+    }
+
+    #[test]
+    fn spatialize_pans_towards_the_sound_and_fades_with_distance() {
+        let listener = Listener { x: 100.0, y: 100.0 };
+
+        let (volume, gains) = listener.spatialize((100.0, 100.0));
+        assert_close(volume, 1.0);
+        assert_eq!(gains, [1.0, 1.0]);
+
+        // Right of the listener, half the hearing distance away
+        let (volume, [left, right]) = listener.spatialize((100.0 + HEARING_DISTANCE / 2.0, 100.0));
+        assert_close(volume, 0.5);
+        assert_close(left, 1.0 - MAX_PAN);
+        assert_close(right, 1.0);
+
+        // Straight above isn't panned at all
+        let (_, gains) = listener.spatialize((100.0, 0.0));
+        assert_eq!(gains, [1.0, 1.0]);
+
+        // Far away on the left, the sound stays audible
+        let (volume, [left, right]) = listener.spatialize((100.0 - HEARING_DISTANCE * 2.0, 100.0));
+        assert_close(volume, MIN_DISTANCE_VOLUME);
+        assert_close(left, 1.0);
+        assert_close(right, 1.0 - MAX_PAN);
+    }
+
+    #[test]
+    fn positioned_sounds_play_attenuated_around_the_listener() {
+        let backend = CaptureBackend::new();
+        let log = backend.log();
+        let mut audio = AudioManager::new(SoundBank::load("assets/sounds/manifest.txt").unwrap(), Box::new(backend));
+        audio.set_listener(Listener { x: 0.0, y: 0.0 });
+
+        audio.play_sound(Bus::Sfx, SoundId::Explosion, PlayParams::default());
+        audio.play_sound(Bus::Sfx, SoundId::Explosion, PlayParams::default().at(HEARING_DISTANCE / 4.0, 0.0));
+        let sounds = log.sounds();
+        assert_close(sounds[0].volume, 1.0);
+        assert_close(sounds[1].volume, 0.75);
+    }
+}
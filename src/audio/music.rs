@@ -0,0 +1,392 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rodio::decoder::LoopedDecoder;
+use rodio::{Decoder, Source};
+
+use crate::audio::tracker::{Module, TrackerControls, TrackerPlayer};
+use crate::audio::{AudioManager, Bus, VoiceId};
+use crate::diagnostics::{log_debug, log_info, log_warn};
+
+/// Frames over which the old track fades out and the new one fades in.
+const CROSSFADE_FRAMES: f32 = 90.0;
+/// Music volume while ducked under an important sound effect.
+const DUCK_VOLUME: f32 = 0.35;
+/// Frames the music takes to recover from being ducked.
+const DUCK_RECOVERY_FRAMES: f32 = 45.0;
+/// Sample rate tracker modules are rendered at.
+const TRACKER_SAMPLE_RATE: u32 = 44100;
+
+type MusicSource = Box<dyn Source<Item = f32> + Send>;
+
+/// A piece of music which loops forever, optionally after playing an intro once.
+///
+/// Tracker modules (`.mod` and `.xm`) loop by themselves from their restart position, and
+/// their channels can be muted while they play. Any other file is decoded by rodio.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MusicTrack {
+    /// The looping part of the track.
+    pub path: PathBuf,
+    /// Played once before the loop starts. Must have the same channels and sample rate.
+    pub intro: Option<PathBuf>,
+}
+
+impl MusicTrack {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), intro: None }
+    }
+
+    fn is_module(&self) -> bool {
+        let extension = self.path.extension().and_then(|extension| extension.to_str()).unwrap_or("");
+        extension.eq_ignore_ascii_case("mod") || extension.eq_ignore_ascii_case("xm")
+    }
+
+    /// Opens the track for playback, with the controls of its channels if it is a module.
+    fn open(&self) -> io::Result<(MusicSource, Option<TrackerControls>)> {
+        if self.is_module() {
+            if self.intro.is_some() {
+                log_warn!(Audio, "Ignoring the intro of {}, modules can't have one", self.path.display());
+            }
+            let module = Module::load(&self.path)?;
+            log_info!(Audio, "Loaded module \"{}\" with {} channels", module.name(), module.channels());
+            let player = TrackerPlayer::new(module, TRACKER_SAMPLE_RATE);
+            let controls = player.controls();
+            return Ok((Box::new(player), Some(controls)));
+        }
+        Ok((Box::new(self.open_stream()?.convert_samples()), None))
+    }
+
+    /// Opens a sound file for streaming, so only a small part of it is decoded at a time.
+    fn open_stream(&self) -> io::Result<IntroLoop> {
+        let intro = match &self.intro {
+            Some(intro) => Some(Decoder::new(open_file(intro)?).map_err(invalid_data)?),
+            None => None,
+        };
+        let body = Decoder::new_looped(open_file(&self.path)?).map_err(invalid_data)?;
+        Ok(IntroLoop { intro, body })
+    }
+}
+
+pub(super) fn open_file(path: &Path) -> io::Result<BufReader<File>> {
+    Ok(BufReader::new(File::open(path)?))
+}
+
+pub(super) fn invalid_data(error: rodio::decoder::DecoderError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Plays an optional intro once, then loops the body of a track forever.
+struct IntroLoop {
+    intro: Option<Decoder<BufReader<File>>>,
+    body: LoopedDecoder<BufReader<File>>,
+}
+
+impl Iterator for IntroLoop {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if let Some(intro) = self.intro.as_mut() {
+            match intro.next() {
+                Some(sample) => return Some(sample),
+                None => self.intro = None,
+            }
+        }
+        self.body.next()
+    }
+}
+
+impl Source for IntroLoop {
+    fn current_frame_len(&self) -> Option<usize> {
+        match &self.intro {
+            Some(intro) => intro.current_frame_len(),
+            None => self.body.current_frame_len(),
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        match &self.intro {
+            Some(intro) => intro.channels(),
+            None => self.body.channels(),
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        match &self.intro {
+            Some(intro) => intro.sample_rate(),
+            None => self.body.sample_rate(),
+        }
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+struct PlayingTrack {
+    voice: VoiceId,
+    controls: Option<TrackerControls>,
+    // Crossfade volume from 0.0 to 1.0
+    fade: f32,
+}
+
+/// The music side of the `AudioManager`: the current track, the tracks fading out behind
+/// it, and how far the music is ducked.
+pub(super) struct MusicState {
+    // The last track asked for, even if it failed to open, so it isn't retried every frame
+    requested: Option<MusicTrack>,
+    current: Option<PlayingTrack>,
+    fading_out: Vec<PlayingTrack>,
+    duck: f32,
+}
+
+impl Default for MusicState {
+    fn default() -> Self {
+        Self { requested: None, current: None, fading_out: Vec::new(), duck: 1.0 }
+    }
+}
+
+impl AudioManager {
+    /// Switches the music to the given track, crossfading from the current one. Passing the
+    /// track which is already playing does nothing, and `None` fades the music out.
+    pub fn play_music(&mut self, track: Option<&MusicTrack>) {
+        if self.music.requested.as_ref() == track {
+            return;
+        }
+        self.music.requested = track.cloned();
+
+        if let Some(previous) = self.music.current.take() {
+            self.music.fading_out.push(previous);
+        }
+
+        let Some(track) = track else {
+            return;
+        };
+
+        match track.open() {
+            Ok((source, controls)) => {
+                if let Some(voice) = self.play_with_volume(Bus::Music, source, 0.0) {
+                    log_info!(Audio, "Playing music {}", track.path.display());
+                    self.music.current = Some(PlayingTrack { voice, controls, fade: 0.0 });
+                }
+            }
+            Err(error) => log_warn!(Audio, "Failed to play music {}: {}", track.path.display(), error),
+        }
+    }
+
+    /// Mutes or unmutes a channel of the current music, to bring layers of a tracker module in
+    /// and out. Does nothing for music which isn't a module.
+    pub fn set_music_channel_muted(&mut self, channel: usize, muted: bool) {
+        if let Some(controls) = self.music.current.as_ref().and_then(|playing| playing.controls.as_ref()) {
+            if controls.is_channel_muted(channel) != muted {
+                log_debug!(Audio, "{} music channel {}", if muted { "Muting" } else { "Unmuting" }, channel + 1);
+                controls.set_channel_muted(channel, muted);
+            }
+        }
+    }
+
+    /// Lowers the music for a moment, so an important sound effect stands out.
+    pub fn duck_music(&mut self) {
+        self.music.duck = DUCK_VOLUME;
+    }
+
+    /// Advances crossfades and ducking by one frame.
+    pub(super) fn update_music(&mut self) {
+        let step = 1.0 / CROSSFADE_FRAMES;
+        self.music.duck = (self.music.duck + (1.0 - DUCK_VOLUME) / DUCK_RECOVERY_FRAMES).min(1.0);
+        let duck = self.music.duck;
+
+        let mut updates = Vec::new();
+        if let Some(current) = self.music.current.as_mut() {
+            current.fade = (current.fade + step).min(1.0);
+            updates.push((current.voice, current.fade * duck));
+        }
+
+        let mut finished = Vec::new();
+        self.music.fading_out.retain_mut(|track| {
+            track.fade = (track.fade - step).max(0.0);
+            if track.fade > 0.0 {
+                updates.push((track.voice, track.fade * duck));
+                true
+            } else {
+                finished.push(track.voice);
+                false
+            }
+        });
+
+        for (voice, volume) in updates {
+            self.set_voice_volume(voice, volume);
+        }
+        for voice in finished {
+            self.stop(voice);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::tests::held_audio;
+
+    const MEADOW: &str = "assets/music/meadow.mod";
+    const BLAST: &str = "assets/music/blast.mod";
+    const TOWERS: &str = "assets/music/towers.wav";
+    const TOWERS_INTRO: &str = "assets/music/towers_intro.wav";
+
+    fn towers() -> MusicTrack {
+        MusicTrack { path: PathBuf::from(TOWERS), intro: Some(PathBuf::from(TOWERS_INTRO)) }
+    }
+
+    fn decode(path: &str) -> Vec<i16> {
+        Decoder::new(open_file(Path::new(path)).unwrap()).unwrap().collect()
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn shipped_tracks_open_as_modules() {
+        for path in [MEADOW, BLAST] {
+            let (_, controls) = MusicTrack::new(path).open().unwrap();
+            assert!(controls.is_some(), "{} has no channel controls", path);
+        }
+    }
+
+    #[test]
+    fn shipped_sound_file_tracks_open_as_streams() {
+        let (_, controls) = towers().open().unwrap();
+        assert!(controls.is_none());
+    }
+
+    #[test]
+    fn intro_plays_once_before_the_body_loops() {
+        let intro = decode(TOWERS_INTRO);
+        let body = decode(TOWERS);
+        assert!(!intro.is_empty() && !body.is_empty());
+
+        let samples: Vec<i16> = towers().open_stream().unwrap().take(intro.len() + 3 * body.len()).collect();
+        assert_eq!(samples[..intro.len()], intro[..]);
+        for repeat in samples[intro.len()..].chunks(body.len()) {
+            assert_eq!(repeat, &body[..]);
+        }
+    }
+
+    #[test]
+    fn new_track_fades_in() {
+        let (mut audio, voices) = held_audio();
+        audio.play_music(Some(&MusicTrack::new(MEADOW)));
+        assert_eq!(voices.lock().unwrap()[0].volume, 0.0);
+
+        audio.update_music();
+        assert_close(voices.lock().unwrap()[0].volume, 1.0 / CROSSFADE_FRAMES);
+
+        for _ in 1..CROSSFADE_FRAMES as usize / 2 {
+            audio.update_music();
+        }
+        assert_close(voices.lock().unwrap()[0].volume, 0.5);
+
+        for _ in 0..CROSSFADE_FRAMES as usize {
+            audio.update_music();
+        }
+        assert_close(voices.lock().unwrap()[0].volume, 1.0);
+    }
+
+    #[test]
+    fn switching_tracks_crossfades_and_stops_the_old_one() {
+        let (mut audio, voices) = held_audio();
+        audio.play_music(Some(&MusicTrack::new(MEADOW)));
+        for _ in 0..CROSSFADE_FRAMES as usize {
+            audio.update_music();
+        }
+
+        // Asking for the playing track again changes nothing
+        audio.play_music(Some(&MusicTrack::new(MEADOW)));
+        assert_eq!(voices.lock().unwrap().len(), 1);
+
+        audio.play_music(Some(&MusicTrack::new(BLAST)));
+        for _ in 0..CROSSFADE_FRAMES as usize / 3 {
+            audio.update_music();
+        }
+        {
+            let voices = voices.lock().unwrap();
+            assert_close(voices[0].volume, 2.0 / 3.0);
+            assert_close(voices[1].volume, 1.0 / 3.0);
+            assert!(!voices[0].stopped);
+        }
+
+        for _ in 0..CROSSFADE_FRAMES as usize {
+            audio.update_music();
+        }
+        let voices = voices.lock().unwrap();
+        assert!(voices[0].stopped);
+        assert_close(voices[1].volume, 1.0);
+        assert!(audio.music.fading_out.is_empty());
+    }
+
+    #[test]
+    fn no_track_fades_the_music_out() {
+        let (mut audio, voices) = held_audio();
+        audio.play_music(Some(&MusicTrack::new(MEADOW)));
+        for _ in 0..CROSSFADE_FRAMES as usize {
+            audio.update_music();
+        }
+
+        audio.play_music(None);
+        for _ in 0..CROSSFADE_FRAMES as usize {
+            audio.update_music();
+        }
+        assert!(voices.lock().unwrap()[0].stopped);
+        assert!(audio.music.current.is_none());
+    }
+
+    #[test]
+    fn ducked_music_recovers_gradually() {
+        let (mut audio, voices) = held_audio();
+        audio.play_music(Some(&MusicTrack::new(MEADOW)));
+        for _ in 0..CROSSFADE_FRAMES as usize {
+            audio.update_music();
+        }
+
+        audio.duck_music();
+        audio.update_music();
+        let recovery_step = (1.0 - DUCK_VOLUME) / DUCK_RECOVERY_FRAMES;
+        assert_close(voices.lock().unwrap()[0].volume, DUCK_VOLUME + recovery_step);
+
+        for _ in 1..DUCK_RECOVERY_FRAMES as usize / 2 {
+            audio.update_music();
+        }
+        let halfway = DUCK_VOLUME + recovery_step * (DUCK_RECOVERY_FRAMES as usize / 2) as f32;
+        assert_close(voices.lock().unwrap()[0].volume, halfway);
+
+        for _ in 0..DUCK_RECOVERY_FRAMES as usize {
+            audio.update_music();
+        }
+        assert_close(voices.lock().unwrap()[0].volume, 1.0);
+    }
+
+    #[test]
+    fn missing_track_is_not_retried_every_frame() {
+        let (mut audio, voices) = held_audio();
+        let missing = MusicTrack::new("assets/music/missing.ogg");
+        audio.play_music(Some(&missing));
+        audio.play_music(Some(&missing));
+        assert!(voices.lock().unwrap().is_empty());
+        assert_eq!(audio.music.requested, Some(missing));
+    }
+
+    #[test]
+    fn channels_of_the_current_module_can_be_muted() {
+        let (mut audio, _) = held_audio();
+        audio.play_music(Some(&MusicTrack::new(MEADOW)));
+        audio.set_music_channel_muted(3, true);
+
+        let controls = audio.music.current.as_ref().and_then(|playing| playing.controls.clone()).unwrap();
+        assert!(controls.is_channel_muted(3));
+        assert!(!controls.is_channel_muted(0));
+
+        audio.set_music_channel_muted(3, false);
+        assert!(!controls.is_channel_muted(3));
+    }
+}
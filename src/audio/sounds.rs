@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::{Decoder, Source};
+
+use crate::audio::synth::SynthParams;
+use crate::diagnostics::{log_info, log_warn};
+use crate::random::Rng;
+
+/// The sound effects the game plays, by the name they are listed under in the manifest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SoundId {
+    Walk1,
+    Walk2,
+    Walk3,
+    Walk4,
+    StepBox1,
+    StepBox2,
+    Jump,
+    FallMild,
+    FallHeavy,
+    Down,
+    Explosion,
+    Kick,
+    KickBox,
+    Toggle,
+    BoxBreak,
+}
+
+impl SoundId {
+    pub const ALL: [SoundId; 15] = [
+        SoundId::Walk1,
+        SoundId::Walk2,
+        SoundId::Walk3,
+        SoundId::Walk4,
+        SoundId::StepBox1,
+        SoundId::StepBox2,
+        SoundId::Jump,
+        SoundId::FallMild,
+        SoundId::FallHeavy,
+        SoundId::Down,
+        SoundId::Explosion,
+        SoundId::Kick,
+        SoundId::KickBox,
+        SoundId::Toggle,
+        SoundId::BoxBreak,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SoundId::Walk1 => "walk_1",
+            SoundId::Walk2 => "walk_2",
+            SoundId::Walk3 => "walk_3",
+            SoundId::Walk4 => "walk_4",
+            SoundId::StepBox1 => "step_box_1",
+            SoundId::StepBox2 => "step_box_2",
+            SoundId::Jump => "jump",
+            SoundId::FallMild => "fall_mild",
+            SoundId::FallHeavy => "fall_heavy",
+            SoundId::Down => "down",
+            SoundId::Explosion => "explosion",
+            SoundId::Kick => "kick",
+            SoundId::KickBox => "kick_box",
+            SoundId::Toggle => "toggle",
+            SoundId::BoxBreak => "box_break",
+        }
+    }
+
+    /// Whether the music is ducked while the sound plays, so it isn't drowned out.
+    pub fn ducks_music(self) -> bool {
+        matches!(self, SoundId::Explosion | SoundId::FallHeavy)
+    }
+}
+
+/// How a sound is played.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayParams {
+    /// Volume relative to the bus, where 1.0 is the volume the sound was recorded at.
+    pub volume: f32,
+    /// Playback speed, which shifts the pitch: 2.0 is an octave up, 0.5 an octave down.
+    pub pitch: f32,
+    /// Where in the world the sound comes from, to pan and attenuate it relative to the
+    /// listener. `None` plays it as is, e.g. for sounds made by the player.
+    pub position: Option<(f32, f32)>,
+}
+
+impl Default for PlayParams {
+    fn default() -> Self {
+        Self { volume: 1.0, pitch: 1.0, position: None }
+    }
+}
+
+impl PlayParams {
+    pub fn volume(mut self, volume: f32) -> Self {
+        self.volume = volume;
+        self
+    }
+
+    pub fn pitch(mut self, pitch: f32) -> Self {
+        self.pitch = pitch;
+        self
+    }
+
+    pub fn at(mut self, x: f32, y: f32) -> Self {
+        self.position = Some((x, y));
+        self
+    }
+}
+
+/// A fully decoded sound, kept in memory as interleaved samples.
+///
+/// Cloning is cheap, as the samples are shared, so any number of voices can play the same
+/// buffer at once without decoding or copying it again.
+#[derive(Debug, Clone)]
+pub struct SoundBuffer {
+    channels: u16,
+    sample_rate: u32,
+    samples: Arc<[f32]>,
+}
+
+impl SoundBuffer {
+    /// Decodes a sound file in any format rodio supports.
+    pub fn decode(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let decoder = Decoder::new(BufReader::new(file)).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        let (channels, sample_rate) = (decoder.channels(), decoder.sample_rate());
+        let samples: Vec<f32> = decoder.convert_samples().collect();
+        Ok(Self::from_samples(channels, sample_rate, samples))
+    }
+
+    pub fn from_samples(channels: u16, sample_rate: u32, samples: Vec<f32>) -> Self {
+        Self { channels, sample_rate, samples: samples.into() }
+    }
+
+    pub fn duration(&self) -> Duration {
+        let frames = self.samples.len() / self.channels.max(1) as usize;
+        Duration::from_secs_f64(frames as f64 / self.sample_rate.max(1) as f64)
+    }
+
+    /// Returns a source which plays the buffer from the start.
+    pub fn source(&self) -> BufferSource {
+        BufferSource { buffer: self.clone(), position: 0 }
+    }
+}
+
+/// Plays a `SoundBuffer`.
+pub struct BufferSource {
+    buffer: SoundBuffer,
+    position: usize,
+}
+
+impl Iterator for BufferSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.buffer.samples.get(self.position).copied();
+        self.position += 1;
+        sample
+    }
+}
+
+impl Source for BufferSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.buffer.samples.len().saturating_sub(self.position))
+    }
+
+    fn channels(&self) -> u16 {
+        self.buffer.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.buffer.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(self.buffer.duration())
+    }
+}
+
+/// A sound in a `SoundBank`.
+#[derive(Debug, Clone)]
+pub enum Sound {
+    /// Decoded from a sound file.
+    Buffer(SoundBuffer),
+    /// Synthesized anew on every play, so it can vary each time.
+    Synth(SynthParams),
+}
+
+impl Sound {
+    /// Loads a synthesizer preset from a `.sfx` path, and decodes any other sound file.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        if path.extension().is_some_and(|extension| extension == "sfx") {
+            SynthParams::load(path).map(Sound::Synth)
+        } else {
+            SoundBuffer::decode(path).map(Sound::Buffer)
+        }
+    }
+
+    /// Returns a source which plays the sound, varying synthesized sounds with `rng`.
+    pub fn source(&self, rng: &mut Rng) -> Box<dyn Source<Item = f32> + Send> {
+        match self {
+            Sound::Buffer(buffer) => Box::new(buffer.source()),
+            Sound::Synth(synth) => Box::new(synth.randomized(rng).source()),
+        }
+    }
+}
+
+/// All sounds listed in a manifest, decoded once at load time.
+///
+/// The manifest has one `name = path` line per sound, with paths relative to the working
+/// directory. Paths ending in `.sfx` are synthesizer presets, anything else is a sound file.
+/// Empty lines and lines starting with `#` are ignored.
+#[derive(Default)]
+pub struct SoundBank {
+    sounds: HashMap<String, Sound>,
+}
+
+impl SoundBank {
+    pub fn load(manifest_path: &str) -> io::Result<Self> {
+        let manifest = fs::read_to_string(manifest_path)?;
+        let mut sounds = HashMap::new();
+
+        for (number, line) in manifest.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((name, path)) = line.split_once('=') else {
+                log_warn!(Audio, "Ignoring line {} of {}: expected `name = path`", number + 1, manifest_path);
+                continue;
+            };
+
+            let (name, path) = (name.trim(), path.trim());
+            let path = Path::new(path);
+            let sound = Sound::load(path).map_err(|error| io::Error::new(error.kind(), format!("Failed to load sound {} from {}: {}", name, path.display(), error)))?;
+            sounds.insert(name.to_string(), sound);
+        }
+
+        for id in SoundId::ALL {
+            if !sounds.contains_key(id.name()) {
+                log_warn!(Audio, "Sound {} is missing from {}", id.name(), manifest_path);
+            }
+        }
+
+        log_info!(Audio, "Loaded {} sounds from {}", sounds.len(), manifest_path);
+        Ok(Self { sounds })
+    }
+
+    pub fn get(&self, id: SoundId) -> Option<&Sound> {
+        self.get_by_name(id.name())
+    }
+
+    /// Looks up a sound by its manifest name, including sounds without a `SoundId`.
+    pub fn get_by_name(&self, name: &str) -> Option<&Sound> {
+        self.sounds.get(name)
+    }
+}
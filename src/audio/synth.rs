@@ -0,0 +1,335 @@
+use std::f32::consts::TAU;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use rodio::Source;
+
+use crate::random::Rng;
+
+const SAMPLE_RATE: u32 = 44100;
+
+/// Shape of the synthesizer's oscillator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Saw,
+    Sine,
+    Noise,
+}
+
+impl Waveform {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "square" => Some(Waveform::Square),
+            "saw" => Some(Waveform::Saw),
+            "sine" => Some(Waveform::Sine),
+            "noise" => Some(Waveform::Noise),
+            _ => None,
+        }
+    }
+}
+
+/// Parameters of a synthesized sound effect, in the spirit of sfxr.
+///
+/// A single oscillator is shaped by an attack, sustain and decay envelope, slides in pitch
+/// and wobbles with vibrato. Presets are `key = value` files, with the keys named after the
+/// fields and the waveform given by name:
+///
+/// ```text
+/// # A rising jump
+/// wave = square
+/// frequency = 320
+/// slide = 2.5
+/// sustain = 0.06
+/// decay = 0.14
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SynthParams {
+    pub wave: Waveform,
+    /// Starting pitch in Hz.
+    pub frequency: f32,
+    /// Lowest pitch a downwards slide reaches, in Hz.
+    pub min_frequency: f32,
+    /// Pitch change in octaves per second, negative to slide down.
+    pub slide: f32,
+    /// Fraction of each period the square wave is high.
+    pub duty: f32,
+    /// Vibrato depth in semitones.
+    pub vibrato_depth: f32,
+    /// Vibrato speed in Hz.
+    pub vibrato_speed: f32,
+    /// Seconds the volume takes to rise.
+    pub attack: f32,
+    /// Seconds the volume holds.
+    pub sustain: f32,
+    /// Extra volume at the start of the sustain, fading to none at its end.
+    pub punch: f32,
+    /// Seconds the volume takes to fall to silence.
+    pub decay: f32,
+    pub volume: f32,
+    /// How much the pitch and timing vary on every play, as a fraction.
+    pub randomize: f32,
+}
+
+impl Default for SynthParams {
+    fn default() -> Self {
+        Self {
+            wave: Waveform::Square,
+            frequency: 440.0,
+            min_frequency: 20.0,
+            slide: 0.0,
+            duty: 0.5,
+            vibrato_depth: 0.0,
+            vibrato_speed: 0.0,
+            attack: 0.0,
+            sustain: 0.1,
+            punch: 0.0,
+            decay: 0.2,
+            volume: 0.5,
+            randomize: 0.0,
+        }
+    }
+}
+
+impl SynthParams {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    /// Parses a preset, starting from the default for every key it leaves out.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut params = Self::default();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = line.split_once('=').ok_or_else(|| format!("line {}: expected `key = value`", number + 1))?;
+            let (key, value) = (key.trim(), value.trim());
+
+            if key == "wave" {
+                params.wave = Waveform::from_name(value).ok_or_else(|| format!("line {}: unknown wave {}", number + 1, value))?;
+                continue;
+            }
+
+            let value: f32 = value.parse().map_err(|_| format!("line {}: {} is not a number", number + 1, value))?;
+            let field = match key {
+                "frequency" => &mut params.frequency,
+                "min_frequency" => &mut params.min_frequency,
+                "slide" => &mut params.slide,
+                "duty" => &mut params.duty,
+                "vibrato_depth" => &mut params.vibrato_depth,
+                "vibrato_speed" => &mut params.vibrato_speed,
+                "attack" => &mut params.attack,
+                "sustain" => &mut params.sustain,
+                "punch" => &mut params.punch,
+                "decay" => &mut params.decay,
+                "volume" => &mut params.volume,
+                "randomize" => &mut params.randomize,
+                _ => return Err(format!("line {}: unknown key {}", number + 1, key)),
+            };
+            *field = value;
+        }
+
+        Ok(params)
+    }
+
+    /// Returns a variation of the sound, with pitch and timing changed by up to `randomize`.
+    pub fn randomized(&self, rng: &mut Rng) -> Self {
+        let amount = self.randomize.clamp(0.0, 1.0);
+        if amount == 0.0 {
+            return self.clone();
+        }
+
+        let mut vary = |value: f32| value * (1.0 + rng.range(-amount, amount));
+        Self {
+            frequency: vary(self.frequency),
+            slide: vary(self.slide),
+            vibrato_speed: vary(self.vibrato_speed),
+            sustain: vary(self.sustain),
+            decay: vary(self.decay),
+            ..self.clone()
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f32(self.attack.max(0.0) + self.sustain.max(0.0) + self.decay.max(0.0))
+    }
+
+    /// Returns a source which synthesizes the sound as it plays.
+    pub fn source(&self) -> SynthSource {
+        let total_samples = (self.duration().as_secs_f32() * SAMPLE_RATE as f32) as usize;
+        SynthSource { params: self.clone(), rng: Rng::from_time(), position: 0, total_samples, phase: 0.0, noise: 0.0, noise_segment: 0 }
+    }
+}
+
+/// Plays `SynthParams`, one mono sample at a time.
+pub struct SynthSource {
+    params: SynthParams,
+    rng: Rng,
+    position: usize,
+    total_samples: usize,
+    // Position within the current period, from 0.0 to 1.0
+    phase: f32,
+    noise: f32,
+    noise_segment: u32,
+}
+
+impl SynthSource {
+    fn envelope(&self, time: f32) -> f32 {
+        let params = &self.params;
+        if time < params.attack {
+            return time / params.attack;
+        }
+        let time = time - params.attack;
+        if time < params.sustain {
+            return 1.0 + params.punch * (1.0 - time / params.sustain);
+        }
+        let time = time - params.sustain;
+        if params.decay > 0.0 { (1.0 - time / params.decay).max(0.0) } else { 0.0 }
+    }
+
+    fn frequency(&self, time: f32) -> f32 {
+        let params = &self.params;
+        let slid = (params.frequency * 2f32.powf(params.slide * time)).max(params.min_frequency);
+        let vibrato = params.vibrato_depth / 12.0 * (TAU * params.vibrato_speed * time).sin();
+        slid * 2f32.powf(vibrato)
+    }
+}
+
+impl Iterator for SynthSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position >= self.total_samples {
+            return None;
+        }
+
+        let time = self.position as f32 / SAMPLE_RATE as f32;
+        self.position += 1;
+
+        self.phase = (self.phase + self.frequency(time) / SAMPLE_RATE as f32).fract();
+
+        let value = match self.params.wave {
+            Waveform::Square => if self.phase < self.params.duty { 1.0 } else { -1.0 },
+            Waveform::Saw => 1.0 - 2.0 * self.phase,
+            Waveform::Sine => (TAU * self.phase).sin(),
+            Waveform::Noise => {
+                // Pick a new random value 32 times per period, so the noise follows the pitch
+                let segment = (self.phase * 32.0) as u32;
+                if segment != self.noise_segment {
+                    self.noise_segment = segment;
+                    self.noise = self.rng.range(-1.0, 1.0);
+                }
+                self.noise
+            }
+        };
+
+        Some(value * self.envelope(time) * self.params.volume)
+    }
+}
+
+impl Source for SynthSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.total_samples - self.position)
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(self.params.duration())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn parse_overrides_defaults_and_skips_comments() {
+        let params = SynthParams::parse("# comment\n\nwave = noise\n  frequency=200 \ndecay = 0.5").unwrap();
+        assert_eq!(params, SynthParams { wave: Waveform::Noise, frequency: 200.0, decay: 0.5, ..SynthParams::default() });
+    }
+
+    #[test]
+    fn parse_reports_the_offending_line() {
+        assert_eq!(SynthParams::parse("wave = square\nwave = triangle").unwrap_err(), "line 2: unknown wave triangle");
+        assert_eq!(SynthParams::parse("pitch = 3").unwrap_err(), "line 1: unknown key pitch");
+        assert_eq!(SynthParams::parse("decay = long").unwrap_err(), "line 1: long is not a number");
+        assert_eq!(SynthParams::parse("decay 0.5").unwrap_err(), "line 1: expected `key = value`");
+    }
+
+    #[test]
+    fn shipped_presets_parse() {
+        for entry in fs::read_dir("assets/sounds").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|extension| extension == "sfx") {
+                let params = SynthParams::load(&path).unwrap_or_else(|error| panic!("{}: {}", path.display(), error));
+                assert!(params.duration() > Duration::ZERO, "{} is silent", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn envelope_rises_holds_with_punch_and_decays() {
+        let params = SynthParams { attack: 0.1, sustain: 0.2, punch: 0.5, decay: 0.4, ..SynthParams::default() };
+        let source = params.source();
+        assert_close(source.envelope(0.0), 0.0);
+        assert_close(source.envelope(0.05), 0.5);
+        assert_close(source.envelope(0.1), 1.5);
+        assert_close(source.envelope(0.2), 1.25);
+        assert_close(source.envelope(0.5), 0.5);
+        assert_close(source.envelope(0.7), 0.0);
+        assert_close(source.envelope(2.0), 0.0);
+    }
+
+    #[test]
+    fn slide_changes_pitch_by_octaves_and_stops_at_the_minimum() {
+        let rising = SynthParams { frequency: 100.0, slide: 2.0, ..SynthParams::default() }.source();
+        assert_close(rising.frequency(0.0), 100.0);
+        assert_close(rising.frequency(0.5), 200.0);
+        assert_close(rising.frequency(1.0), 400.0);
+
+        let falling = SynthParams { frequency: 400.0, slide: -1.0, min_frequency: 150.0, ..SynthParams::default() }.source();
+        assert_close(falling.frequency(1.0), 200.0);
+        assert_close(falling.frequency(3.0), 150.0);
+    }
+
+    #[test]
+    fn source_plays_for_the_sound_duration() {
+        let params = SynthParams { attack: 0.01, sustain: 0.02, decay: 0.03, ..SynthParams::default() };
+        let samples: Vec<f32> = params.source().collect();
+        assert_eq!(samples.len(), (0.06 * SAMPLE_RATE as f32) as usize);
+        assert!(samples.iter().all(|sample| sample.abs() <= params.volume));
+    }
+
+    #[test]
+    fn randomized_varies_within_the_amount() {
+        let params = SynthParams { randomize: 0.1, ..SynthParams::default() };
+        let mut rng = Rng::new(3);
+        for _ in 0..100 {
+            let varied = params.randomized(&mut rng);
+            assert!((varied.frequency - params.frequency).abs() <= params.frequency * 0.1);
+            assert!((varied.decay - params.decay).abs() <= params.decay * 0.1);
+            assert_eq!(varied.wave, params.wave);
+        }
+
+        let fixed = SynthParams::default();
+        assert_eq!(fixed.randomized(&mut rng), fixed);
+    }
+}
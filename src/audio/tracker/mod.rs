@@ -0,0 +1,948 @@
+//! A player for tracker modules in the ProTracker MOD and FastTracker II XM formats.
+//!
+//! The player renders the module itself, so it plays as an ordinary rodio `Source` on any bus.
+//! It follows the song's order list including position jumps, pattern breaks and pattern
+//! loops, and starts over from the module's restart position when the song ends. Channels can
+//! be muted while the module plays through `TrackerControls`, to bring layers of the music in
+//! and out.
+
+use std::f32::consts::{FRAC_PI_2, TAU};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::Source;
+
+mod protracker;
+mod xm;
+
+/// Most channels a module can have, as many as `TrackerControls` can mute.
+const MAX_CHANNELS: usize = 32;
+
+// Effects beyond the ProTracker ones, numbered by their letter in FastTracker II
+const EFFECT_GLOBAL_VOLUME: u8 = 0x10;
+const EFFECT_KEY_OFF: u8 = 0x14;
+
+/// How note periods relate to playback frequencies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrequencyMode {
+    /// Periods of the Amiga's sound chip, where pitch is inversely proportional to the period.
+    Amiga,
+    /// FastTracker II's linear periods, where every semitone is the same number of periods.
+    Linear,
+}
+
+impl FrequencyMode {
+    /// Period of a note, counted in semitones from C-0, where C-4 plays a sample at 8363 Hz.
+    fn note_period(self, note: i32, finetune: i8) -> f32 {
+        let note = note as f32 + finetune as f32 / 128.0;
+        match self {
+            FrequencyMode::Amiga => 428.0 * 2f32.powf((48.0 - note) / 12.0),
+            FrequencyMode::Linear => 7680.0 - note * 64.0,
+        }
+    }
+
+    fn shift(self, period: f32, semitones: i32) -> f32 {
+        match self {
+            FrequencyMode::Amiga => period * 2f32.powf(-semitones as f32 / 12.0),
+            FrequencyMode::Linear => period - semitones as f32 * 64.0,
+        }
+    }
+
+    fn frequency(self, period: f32) -> f32 {
+        let period = period.max(1.0);
+        match self {
+            FrequencyMode::Amiga => 8363.0 * 428.0 / period,
+            FrequencyMode::Linear => 8363.0 * 2f32.powf((4608.0 - period) / 768.0),
+        }
+    }
+
+    /// Periods moved per unit of a portamento or vibrato effect.
+    fn slide_scale(self) -> f32 {
+        match self {
+            FrequencyMode::Amiga => 1.0,
+            FrequencyMode::Linear => 4.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Note {
+    #[default]
+    None,
+    /// Semitones from C-0.
+    On(u8),
+    Off,
+}
+
+/// One channel of one row of a pattern.
+#[derive(Debug, Clone, Copy, Default)]
+struct Cell {
+    note: Note,
+    /// Instrument number starting at 1, or 0 to keep the channel's instrument.
+    instrument: u8,
+    /// FastTracker II's volume column, 0 when empty.
+    volume: u8,
+    effect: u8,
+    param: u8,
+}
+
+struct Pattern {
+    rows: usize,
+    // Row by row, one cell per channel
+    cells: Vec<Cell>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LoopKind {
+    None,
+    Forward,
+    PingPong,
+}
+
+struct Sample {
+    data: Vec<f32>,
+    loop_kind: LoopKind,
+    loop_start: usize,
+    loop_end: usize,
+    /// 0 to 64.
+    volume: u8,
+    /// Fraction of a semitone in 128ths.
+    finetune: i8,
+    relative_note: i8,
+    /// Panning from 0.0 (left) to 1.0 (right), or `None` to keep the channel's panning.
+    panning: Option<f32>,
+}
+
+impl Sample {
+    fn looped(mut self, kind: LoopKind, start: usize, length: usize) -> Self {
+        let end = (start + length).min(self.data.len());
+        if kind != LoopKind::None && start < end {
+            self.loop_kind = kind;
+            self.loop_start = start;
+            self.loop_end = end;
+        }
+        self
+    }
+
+    /// Linearly interpolated sample value at a fractional position.
+    fn value_at(&self, position: f64) -> f32 {
+        let index = position as usize;
+        let Some(&current) = self.data.get(index) else {
+            return 0.0;
+        };
+        let next_index = if self.loop_kind == LoopKind::Forward && index + 1 >= self.loop_end { self.loop_start } else { index + 1 };
+        let next = self.data.get(next_index).copied().unwrap_or(0.0);
+        let fraction = (position - index as f64) as f32;
+        current + (next - current) * fraction
+    }
+}
+
+struct Envelope {
+    /// Ticks and values from 0 to 64.
+    points: Vec<(u16, u8)>,
+    /// Point the envelope holds at while the key is down.
+    sustain: Option<usize>,
+    /// First and last point of the envelope's loop.
+    loop_points: Option<(usize, usize)>,
+}
+
+impl Envelope {
+    fn value_at(&self, tick: u16) -> f32 {
+        let Some(&(_, last)) = self.points.last() else {
+            return 1.0;
+        };
+        let value = self.points.windows(2)
+            .find(|segment| tick < segment[1].0)
+            .map(|segment| {
+                let ((start_tick, start), (end_tick, end)) = (segment[0], segment[1]);
+                let t = (tick.saturating_sub(start_tick)) as f32 / (end_tick.saturating_sub(start_tick)).max(1) as f32;
+                start as f32 + (end as f32 - start as f32) * t
+            })
+            .unwrap_or(last as f32);
+        value / 64.0
+    }
+}
+
+struct Instrument {
+    samples: Vec<Sample>,
+    /// Index of the sample played for each note.
+    keymap: [u8; 96],
+    volume_envelope: Option<Envelope>,
+    /// How fast the volume fades after a key off, out of 65536 per tick.
+    fadeout: u16,
+}
+
+impl Instrument {
+    fn single(sample: Sample) -> Self {
+        Self { samples: vec![sample], keymap: [0; 96], volume_envelope: None, fadeout: 0 }
+    }
+}
+
+/// A parsed tracker module.
+pub struct Module {
+    name: String,
+    channels: usize,
+    /// Patterns in the order they are played.
+    orders: Vec<usize>,
+    /// Order the song continues from after its last one.
+    restart_position: usize,
+    patterns: Vec<Pattern>,
+    instruments: Vec<Instrument>,
+    /// Ticks per row.
+    speed: u32,
+    /// Beats per minute, which sets the length of a tick.
+    tempo: u32,
+    frequency_mode: FrequencyMode,
+    /// Initial panning of each channel.
+    panning: Vec<f32>,
+}
+
+impl Module {
+    /// Loads a MOD or XM module, telling the formats apart by their contents.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        let module = if data.starts_with(xm::SIGNATURE) { xm::parse(data)? } else { protracker::parse(data)? };
+        if module.orders.is_empty() {
+            return Err(invalid_data("module has no orders"));
+        }
+        Ok(module)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn cell(&self, order: usize, row: usize, channel: usize) -> Cell {
+        self.orders.get(order)
+            .and_then(|&pattern| self.patterns.get(pattern))
+            .and_then(|pattern| pattern.cells.get(row * self.channels + channel))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn rows(&self, order: usize) -> usize {
+        self.orders.get(order).and_then(|&pattern| self.patterns.get(pattern)).map_or(64, |pattern| pattern.rows)
+    }
+
+    /// The sample an instrument plays for a note, with its index in the instrument.
+    fn sample(&self, instrument: usize, note: u8) -> Option<(usize, &Sample)> {
+        let instrument = self.instruments.get(instrument)?;
+        let index = *instrument.keymap.get(note as usize)? as usize;
+        instrument.samples.get(index).map(|sample| (index, sample))
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Bounds-checked reads from a module file.
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn position(&self) -> usize {
+        self.position
+    }
+
+    fn seek(&mut self, position: usize) {
+        self.position = position;
+    }
+
+    fn skip(&mut self, count: usize) {
+        self.position += count;
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn bytes(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        let bytes = self.data.get(self.position..self.position + count).ok_or_else(|| invalid_data("unexpected end of module"))?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    /// Reads up to `count` bytes, as modules often end with a truncated sample.
+    fn bytes_truncated(&mut self, count: usize) -> &'a [u8] {
+        let start = self.position.min(self.data.len());
+        let end = (self.position + count).min(self.data.len());
+        self.position += count;
+        &self.data[start..end]
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16_le(&mut self) -> io::Result<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u16_be(&mut self) -> io::Result<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32_le(&mut self) -> io::Result<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn text(&mut self, count: usize) -> io::Result<String> {
+        let bytes = self.bytes(count)?;
+        Ok(String::from_utf8_lossy(bytes).trim_end_matches(['\0', ' ']).to_string())
+    }
+}
+
+/// Mutes and unmutes the channels of a playing module, from outside the audio thread.
+#[derive(Debug, Clone, Default)]
+pub struct TrackerControls {
+    muted: Arc<AtomicU64>,
+}
+
+impl TrackerControls {
+    pub fn set_channel_muted(&self, channel: usize, muted: bool) {
+        if channel >= MAX_CHANNELS {
+            return;
+        }
+        let bit = 1 << channel;
+        if muted {
+            self.muted.fetch_or(bit, Ordering::Relaxed);
+        } else {
+            self.muted.fetch_and(!bit, Ordering::Relaxed);
+        }
+    }
+
+    pub fn is_channel_muted(&self, channel: usize) -> bool {
+        channel < MAX_CHANNELS && self.muted.load(Ordering::Relaxed) & (1 << channel) != 0
+    }
+}
+
+#[derive(Default)]
+struct Channel {
+    /// Instrument selected by the last instrument number.
+    instrument: Option<usize>,
+    /// Last note played, used to pick the instrument's sample.
+    key: u8,
+    /// Instrument and sample currently playing.
+    voice: Option<(usize, usize)>,
+    position: f64,
+    backwards: bool,
+    // Sample frames advanced per output frame
+    step: f64,
+    period: f32,
+    target_period: f32,
+    volume: i32,
+    panning: f32,
+    key_on: bool,
+    envelope_tick: u16,
+    fadeout: u32,
+    // Left and right gain for the current tick
+    gains: (f32, f32),
+
+    // Effect of the current row
+    effect: u8,
+    param: u8,
+    volume_command: u8,
+    delayed: Option<Cell>,
+    arpeggio: i32,
+    vibrato_offset: f32,
+
+    // Parameters remembered for effects repeated without one
+    porta_speed: u8,
+    tone_porta_speed: u8,
+    vibrato_speed: u8,
+    vibrato_depth: u8,
+    vibrato_position: u8,
+    volume_slide: u8,
+    sample_offset: u8,
+    loop_row: usize,
+    loop_count: u8,
+}
+
+impl Channel {
+    fn begin_row(&mut self, cell: Cell) {
+        self.effect = cell.effect;
+        self.param = cell.param;
+        self.volume_command = cell.volume;
+        self.delayed = None;
+        self.arpeggio = 0;
+        self.vibrato_offset = 0.0;
+    }
+
+    /// Plays a cell's note and instrument and applies its first-tick effects.
+    fn trigger(&mut self, module: &Module, cell: Cell) {
+        let tone_porta = matches!(cell.effect, 0x3 | 0x5) || cell.volume >> 4 == 0xF;
+        let (effect, param) = (cell.effect, cell.param);
+
+        if effect == 0x9 && param > 0 {
+            self.sample_offset = param;
+        }
+
+        if cell.instrument > 0 {
+            self.instrument = Some(cell.instrument as usize - 1);
+        }
+
+        if let (Note::On(note), Some(instrument)) = (cell.note, self.instrument) {
+            if let Some((index, sample)) = module.sample(instrument, note) {
+                let period = module.frequency_mode.note_period(note as i32 + sample.relative_note as i32, sample.finetune);
+                self.key = note;
+                if tone_porta && self.voice.is_some() {
+                    self.target_period = period;
+                } else {
+                    self.voice = Some((instrument, index));
+                    self.period = period;
+                    self.target_period = period;
+                    self.backwards = false;
+                    self.vibrato_position = 0;
+                    self.position = if effect == 0x9 { self.sample_offset as f64 * 256.0 } else { 0.0 };
+                    if self.position >= sample.data.len() as f64 {
+                        self.voice = None;
+                    }
+                }
+            }
+        }
+
+        // An instrument number resets the volume and panning, even without a note
+        if let (true, Some(instrument)) = (cell.instrument > 0, self.instrument) {
+            if let Some((_, sample)) = module.sample(instrument, self.key) {
+                self.volume = sample.volume as i32;
+                self.panning = sample.panning.unwrap_or(self.panning);
+            }
+            self.key_on = true;
+            self.envelope_tick = 0;
+            self.fadeout = 65536;
+        }
+
+        if cell.note == Note::Off {
+            self.key_off(module);
+        }
+
+        let volume = cell.volume;
+        match volume >> 4 {
+            0x1..=0x4 => self.volume = volume as i32 - 0x10,
+            0x5 if volume == 0x50 => self.volume = 64,
+            0x8 => self.volume -= (volume & 0xF) as i32,
+            0x9 => self.volume += (volume & 0xF) as i32,
+            0xC => self.panning = (volume & 0xF) as f32 / 15.0,
+            0xF if volume & 0xF > 0 => self.tone_porta_speed = (volume & 0xF) << 4,
+            _ => {}
+        }
+
+        let scale = module.frequency_mode.slide_scale();
+        let (high, low) = (param >> 4, param & 0xF);
+        match effect {
+            0x1 | 0x2 if param > 0 => self.porta_speed = param,
+            0x3 if param > 0 => self.tone_porta_speed = param,
+            0x4 => {
+                if high > 0 {
+                    self.vibrato_speed = high;
+                }
+                if low > 0 {
+                    self.vibrato_depth = low;
+                }
+            }
+            0x8 => self.panning = param as f32 / 255.0,
+            0xC => self.volume = param.min(64) as i32,
+            0xE => match high {
+                0x1 => self.period -= low as f32 * scale,
+                0x2 => self.period += low as f32 * scale,
+                0xA => self.volume += low as i32,
+                0xB => self.volume -= low as i32,
+                0xC if low == 0 => self.volume = 0,
+                _ => {}
+            },
+            EFFECT_KEY_OFF if param == 0 => self.key_off(module),
+            _ => {}
+        }
+        if matches!(effect, 0x5 | 0x6 | 0xA) && param > 0 {
+            self.volume_slide = param;
+        }
+        self.volume = self.volume.clamp(0, 64);
+    }
+
+    /// Applies the row's continuous effects on every tick after the first.
+    fn update_tick(&mut self, module: &Module, tick: u32) {
+        let scale = module.frequency_mode.slide_scale();
+        let (high, low) = (self.param >> 4, self.param & 0xF);
+        match self.effect {
+            0x0 if self.param > 0 => {
+                self.arpeggio = match tick % 3 {
+                    0 => 0,
+                    1 => high as i32,
+                    _ => low as i32,
+                };
+            }
+            0x1 => self.period = (self.period - self.porta_speed as f32 * scale).max(1.0),
+            0x2 => self.period += self.porta_speed as f32 * scale,
+            0x3 | 0x5 => self.tone_portamento(scale),
+            0x4 | 0x6 => {
+                self.vibrato_position = (self.vibrato_position + self.vibrato_speed) % 64;
+                let phase = self.vibrato_position as f32 / 64.0 * TAU;
+                self.vibrato_offset = phase.sin() * self.vibrato_depth as f32 * 2.0 * scale;
+            }
+            0xE if high == 0xC && tick == low as u32 => self.volume = 0,
+            0xE if high == 0xD && tick == low as u32 => {
+                if let Some(cell) = self.delayed.take() {
+                    self.trigger(module, cell);
+                }
+            }
+            EFFECT_KEY_OFF if tick == self.param as u32 => self.key_off(module),
+            _ => {}
+        }
+
+        if matches!(self.effect, 0x5 | 0x6 | 0xA) {
+            let (up, down) = (self.volume_slide >> 4, self.volume_slide & 0xF);
+            self.volume += if up > 0 { up as i32 } else { -(down as i32) };
+        }
+
+        let volume = self.volume_command;
+        match volume >> 4 {
+            0x6 => self.volume -= (volume & 0xF) as i32,
+            0x7 => self.volume += (volume & 0xF) as i32,
+            0xF => self.tone_portamento(scale),
+            _ => {}
+        }
+        self.volume = self.volume.clamp(0, 64);
+    }
+
+    fn tone_portamento(&mut self, scale: f32) {
+        let speed = self.tone_porta_speed as f32 * scale;
+        if self.period < self.target_period {
+            self.period = (self.period + speed).min(self.target_period);
+        } else {
+            self.period = (self.period - speed).max(self.target_period);
+        }
+    }
+
+    fn key_off(&mut self, module: &Module) {
+        self.key_on = false;
+        // Without an envelope to release there's nothing left to play
+        if self.envelope(module).is_none() {
+            self.volume = 0;
+        }
+    }
+
+    fn envelope<'m>(&self, module: &'m Module) -> Option<&'m Envelope> {
+        let (instrument, _) = self.voice?;
+        module.instruments.get(instrument)?.volume_envelope.as_ref()
+    }
+
+    /// Advances the envelope and fadeout, and works out the frequency and gains for the tick.
+    fn finish_tick(&mut self, module: &Module, sample_rate: u32) {
+        let mut envelope_volume = 1.0;
+        if let Some(envelope) = self.envelope(module) {
+            envelope_volume = envelope.value_at(self.envelope_tick);
+
+            let sustained = self.key_on && envelope.sustain.is_some_and(|point| envelope.points[point].0 == self.envelope_tick);
+            if !sustained {
+                self.envelope_tick = self.envelope_tick.saturating_add(1);
+                if let Some((start, end)) = envelope.loop_points {
+                    if self.envelope_tick >= envelope.points[end].0 {
+                        self.envelope_tick = envelope.points[start].0;
+                    }
+                }
+            }
+
+            if !self.key_on {
+                let fadeout = self.voice.and_then(|(instrument, _)| module.instruments.get(instrument)).map_or(0, |instrument| instrument.fadeout);
+                self.fadeout = self.fadeout.saturating_sub(fadeout as u32);
+            }
+        }
+
+        let volume = self.volume as f32 / 64.0 * envelope_volume * self.fadeout as f32 / 65536.0;
+        let angle = self.panning.clamp(0.0, 1.0) * FRAC_PI_2;
+        self.gains = (angle.cos() * volume, angle.sin() * volume);
+
+        let mode = module.frequency_mode;
+        let period = mode.shift(self.period + self.vibrato_offset, self.arpeggio);
+        self.step = mode.frequency(period) as f64 / sample_rate as f64;
+    }
+
+    /// Moves through the sample by one output frame, following its loop.
+    fn advance(&mut self, sample: &Sample) {
+        if self.backwards {
+            self.position -= self.step;
+        } else {
+            self.position += self.step;
+        }
+
+        let (start, end) = (sample.loop_start as f64, sample.loop_end as f64);
+        match sample.loop_kind {
+            LoopKind::None => {
+                if self.position >= sample.data.len() as f64 {
+                    self.voice = None;
+                }
+            }
+            LoopKind::Forward => {
+                if self.position >= end {
+                    self.position = start + (self.position - end) % (end - start);
+                }
+            }
+            LoopKind::PingPong => {
+                if !self.backwards && self.position >= end - 1.0 {
+                    self.position = (2.0 * (end - 1.0) - self.position).max(start);
+                    self.backwards = true;
+                } else if self.backwards && self.position < start {
+                    self.position = (2.0 * start - self.position).min(end - 1.0);
+                    self.backwards = false;
+                }
+            }
+        }
+    }
+}
+
+/// Where the song continues after the current row, set by the row's effects.
+#[derive(Default)]
+struct Jump {
+    order: Option<usize>,
+    row: Option<usize>,
+    loop_row: Option<usize>,
+}
+
+/// Plays a module as a stereo `Source`, looping the song forever.
+pub struct TrackerPlayer {
+    module: Module,
+    controls: TrackerControls,
+    sample_rate: u32,
+    channels: Vec<Channel>,
+    order: usize,
+    row: usize,
+    tick: u32,
+    speed: u32,
+    tempo: u32,
+    global_volume: f32,
+    jump: Jump,
+    frames_until_tick: u32,
+    // The right half of the frame whose left sample was returned last
+    right: Option<f32>,
+}
+
+impl TrackerPlayer {
+    pub fn new(module: Module, sample_rate: u32) -> Self {
+        let channels = module.panning.iter().map(|&panning| Channel { panning, ..Channel::default() }).collect();
+        Self {
+            speed: module.speed.max(1),
+            tempo: module.tempo.max(32),
+            module,
+            controls: TrackerControls::default(),
+            sample_rate,
+            channels,
+            order: 0,
+            row: 0,
+            tick: 0,
+            global_volume: 1.0,
+            jump: Jump::default(),
+            frames_until_tick: 0,
+            right: None,
+        }
+    }
+
+    /// Returns controls which keep working after the player is handed to the mixer.
+    pub fn controls(&self) -> TrackerControls {
+        self.controls.clone()
+    }
+
+    fn process_tick(&mut self) {
+        if self.tick == 0 {
+            self.process_row();
+        } else {
+            for channel in &mut self.channels {
+                channel.update_tick(&self.module, self.tick);
+            }
+        }
+
+        for channel in &mut self.channels {
+            channel.finish_tick(&self.module, self.sample_rate);
+        }
+
+        self.tick += 1;
+        if self.tick >= self.speed {
+            self.tick = 0;
+            self.advance_row();
+        }
+    }
+
+    fn process_row(&mut self) {
+        for index in 0..self.channels.len() {
+            let cell = self.module.cell(self.order, self.row, index);
+            let channel = &mut self.channels[index];
+            channel.begin_row(cell);
+
+            let (high, low) = (cell.param >> 4, cell.param & 0xF);
+            if cell.effect == 0xE && high == 0xD && low > 0 {
+                channel.delayed = Some(cell);
+            } else {
+                channel.trigger(&self.module, cell);
+            }
+
+            match cell.effect {
+                0xB => self.jump.order = Some(cell.param as usize),
+                0xD => self.jump.row = Some(high as usize * 10 + low as usize),
+                0xE if high == 0x6 => {
+                    if low == 0 {
+                        channel.loop_row = self.row;
+                    } else if channel.loop_count == 0 {
+                        channel.loop_count = low;
+                        self.jump.loop_row = Some(channel.loop_row);
+                    } else {
+                        channel.loop_count -= 1;
+                        if channel.loop_count > 0 {
+                            self.jump.loop_row = Some(channel.loop_row);
+                        }
+                    }
+                }
+                0xF if cell.param > 0 => {
+                    if cell.param < 32 {
+                        self.speed = cell.param as u32;
+                    } else {
+                        self.tempo = cell.param as u32;
+                    }
+                }
+                EFFECT_GLOBAL_VOLUME => self.global_volume = cell.param.min(64) as f32 / 64.0,
+                _ => {}
+            }
+        }
+    }
+
+    fn advance_row(&mut self) {
+        let jump = std::mem::take(&mut self.jump);
+        if let Some(row) = jump.loop_row {
+            self.row = row;
+        } else if jump.order.is_some() || jump.row.is_some() {
+            self.order = jump.order.unwrap_or(self.order + 1);
+            self.row = jump.row.unwrap_or(0);
+        } else {
+            self.row += 1;
+            if self.row >= self.module.rows(self.order) {
+                self.row = 0;
+                self.order += 1;
+            }
+        }
+
+        // Loop the song once it ends
+        if self.order >= self.module.orders.len() {
+            self.order = self.module.restart_position;
+        }
+        if self.row >= self.module.rows(self.order) {
+            self.row = 0;
+        }
+    }
+
+    fn mix_frame(&mut self) -> (f32, f32) {
+        let muted = self.controls.muted.load(Ordering::Relaxed);
+        let (mut left, mut right) = (0.0, 0.0);
+
+        for (index, channel) in self.channels.iter_mut().enumerate() {
+            let Some((instrument, sample)) = channel.voice else {
+                continue;
+            };
+            let Some(sample) = self.module.instruments.get(instrument).and_then(|instrument| instrument.samples.get(sample)) else {
+                continue;
+            };
+
+            // Muted channels keep playing silently, so they come back in time
+            if muted & (1 << index) == 0 {
+                let value = sample.value_at(channel.position);
+                left += value * channel.gains.0;
+                right += value * channel.gains.1;
+            }
+            channel.advance(sample);
+        }
+
+        let gain = self.global_volume / (self.channels.len().max(1) as f32).sqrt();
+        (left * gain, right * gain)
+    }
+}
+
+impl Iterator for TrackerPlayer {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if let Some(right) = self.right.take() {
+            return Some(right);
+        }
+
+        if self.frames_until_tick == 0 {
+            self.process_tick();
+            // A tick lasts 2.5 / tempo seconds
+            self.frames_until_tick = (self.sample_rate * 5 / (2 * self.tempo)).max(1);
+        }
+        self.frames_until_tick -= 1;
+
+        let (left, right) = self.mix_frame();
+        self.right = Some(right);
+        Some(left)
+    }
+}
+
+impl Source for TrackerPlayer {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a 4-channel MOD without samples, with effects placed in the first channel.
+    fn build_mod(orders: &[u8], effects: &[(u8, usize, u8, u8)]) -> Vec<u8> {
+        let mut data = vec![0; 1084];
+        data[..4].copy_from_slice(b"test");
+        data[950] = orders.len() as u8;
+        data[952..952 + orders.len()].copy_from_slice(orders);
+        data[1080..1084].copy_from_slice(b"M.K.");
+
+        let pattern_count = *orders.iter().max().unwrap() as usize + 1;
+        let pattern_size = 64 * 4 * 4;
+        data.resize(1084 + pattern_count * pattern_size, 0);
+        for &(pattern, row, effect, param) in effects {
+            let cell = 1084 + pattern as usize * pattern_size + row * 4 * 4;
+            data[cell + 2] = effect;
+            data[cell + 3] = param;
+        }
+        data
+    }
+
+    /// Builds a 2-channel XM without instruments, with patterns of `rows` rows and effects
+    /// placed in the first channel.
+    fn build_xm(orders: &[u8], restart_position: u16, rows: usize, effects: &[(u8, usize, u8, u8)]) -> Vec<u8> {
+        let pattern_count = *orders.iter().max().unwrap() as usize + 1;
+        let mut data = Vec::new();
+        data.extend_from_slice(xm::SIGNATURE);
+        data.extend_from_slice(&[b' '; 20]);
+        data.push(0x1A);
+        data.extend_from_slice(&[b' '; 20]);
+        data.extend_from_slice(&0x0104u16.to_le_bytes());
+        data.extend_from_slice(&276u32.to_le_bytes());
+        for value in [orders.len() as u16, restart_position, 2, pattern_count as u16, 0, 1, 6, 125] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        let mut order_table = [0; 256];
+        order_table[..orders.len()].copy_from_slice(orders);
+        data.extend_from_slice(&order_table);
+
+        for pattern in 0..pattern_count as u8 {
+            let mut packed = Vec::new();
+            for row in 0..rows {
+                match effects.iter().find(|&&(p, r, _, _)| p == pattern && r == row) {
+                    Some(&(_, _, effect, param)) => packed.extend_from_slice(&[0x80 | 0x08 | 0x10, effect, param]),
+                    None => packed.push(0x80),
+                }
+                packed.push(0x80);
+            }
+            data.extend_from_slice(&9u32.to_le_bytes());
+            data.push(0);
+            data.extend_from_slice(&(rows as u16).to_le_bytes());
+            data.extend_from_slice(&(packed.len() as u16).to_le_bytes());
+            data.extend_from_slice(&packed);
+        }
+        data
+    }
+
+    /// The order and row of each of the first `count` rows played.
+    fn visited(module: Module, count: usize) -> Vec<(usize, usize)> {
+        let mut player = TrackerPlayer::new(module, 44100);
+        (0..count).map(|_| {
+            let position = (player.order, player.row);
+            player.process_row();
+            player.advance_row();
+            position
+        }).collect()
+    }
+
+    #[test]
+    fn mod_follows_breaks_jumps_and_loops() {
+        let data = build_mod(&[0, 1, 2], &[
+            // Break to row 12 (in decimal digits) of the next order
+            (0, 2, 0xD, 0x12),
+            // Jump to the third order
+            (1, 13, 0xB, 0x02),
+            // Play rows 0 and 1 of the third order three times
+            (2, 0, 0xE, 0x60),
+            (2, 1, 0xE, 0x62),
+            // Back to the start
+            (2, 3, 0xB, 0x00),
+        ]);
+        let module = Module::parse(&data).unwrap();
+        assert_eq!(module.name(), "test");
+        assert_eq!(module.channels(), 4);
+
+        assert_eq!(visited(module, 14), vec![
+            (0, 0), (0, 1), (0, 2),
+            (1, 12), (1, 13),
+            (2, 0), (2, 1), (2, 0), (2, 1), (2, 0), (2, 1), (2, 2), (2, 3),
+            (0, 0),
+        ]);
+    }
+
+    #[test]
+    fn xm_follows_breaks_and_loops_and_restarts() {
+        let data = build_xm(&[0, 1], 1, 4, &[
+            (0, 1, 0xD, 0x02),
+            (1, 0, 0xE, 0x60),
+            (1, 3, 0xE, 0x61),
+        ]);
+        let module = Module::parse(&data).unwrap();
+        assert_eq!(module.channels(), 2);
+
+        assert_eq!(visited(module, 9), vec![
+            (0, 0), (0, 1),
+            // The loop end is reached before its start, so it loops from the top of the pattern
+            (1, 2), (1, 3), (1, 0), (1, 1), (1, 2), (1, 3),
+            // The song restarts from its second order
+            (1, 0),
+        ]);
+    }
+
+    #[test]
+    fn muted_channels_are_reported() {
+        let controls = TrackerControls::default();
+        controls.set_channel_muted(3, true);
+        assert!(controls.is_channel_muted(3));
+        assert!(!controls.is_channel_muted(2));
+        controls.set_channel_muted(3, false);
+        assert!(!controls.is_channel_muted(3));
+        // Channels beyond MAX_CHANNELS are ignored
+        controls.set_channel_muted(MAX_CHANNELS, true);
+        assert!(!controls.is_channel_muted(MAX_CHANNELS));
+    }
+
+    #[test]
+    fn rejects_unknown_formats() {
+        assert!(Module::parse(&[0; 2000]).is_err());
+        assert!(Module::parse(b"Extended Module: ").is_err());
+    }
+}
@@ -0,0 +1,102 @@
+//! Loads ProTracker MOD files and their variants with up to 32 channels.
+
+use std::io;
+
+use super::{invalid_data, Cell, FrequencyMode, Instrument, LoopKind, Module, Note, Pattern, Reader, Sample, MAX_CHANNELS};
+
+const SAMPLE_COUNT: usize = 31;
+const ROWS: usize = 64;
+
+pub(super) fn parse(data: &[u8]) -> io::Result<Module> {
+    let mut tag = Reader::new(data);
+    tag.seek(1080);
+    let channels = channel_count(tag.bytes(4)?).ok_or_else(|| invalid_data("not a MOD or XM module"))?;
+
+    let mut reader = Reader::new(data);
+    let name = reader.text(20)?;
+
+    let mut samples = Vec::with_capacity(SAMPLE_COUNT);
+    for _ in 0..SAMPLE_COUNT {
+        reader.skip(22);
+        let length = reader.u16_be()? as usize * 2;
+        // A signed nibble in eighths of a semitone
+        let finetune = ((reader.u8()? & 0xF) << 4) as i8;
+        let volume = reader.u8()?.min(64);
+        let loop_start = reader.u16_be()? as usize * 2;
+        let loop_length = reader.u16_be()? as usize * 2;
+        samples.push((length, finetune, volume, loop_start, loop_length));
+    }
+
+    let song_length = (reader.u8()? as usize).clamp(1, 128);
+    let restart_position = reader.u8()? as usize;
+    let order_table = reader.bytes(128)?;
+    reader.skip(4);
+
+    let pattern_count = order_table.iter().copied().max().unwrap_or(0) as usize + 1;
+    let mut patterns = Vec::with_capacity(pattern_count);
+    for _ in 0..pattern_count {
+        let mut cells = Vec::with_capacity(ROWS * channels);
+        for _ in 0..ROWS * channels {
+            let bytes = reader.bytes(4)?;
+            let period = ((bytes[0] & 0x0F) as u16) << 8 | bytes[1] as u16;
+            cells.push(Cell {
+                note: if period > 0 { Note::On(note_from_period(period)) } else { Note::None },
+                instrument: (bytes[0] & 0xF0) | (bytes[2] >> 4),
+                volume: 0,
+                effect: bytes[2] & 0x0F,
+                param: bytes[3],
+            });
+        }
+        patterns.push(Pattern { rows: ROWS, cells });
+    }
+
+    let instruments = samples.into_iter().map(|(length, finetune, volume, loop_start, loop_length)| {
+        let data = reader.bytes_truncated(length).iter().map(|&byte| byte as i8 as f32 / 128.0).collect();
+        let sample = Sample {
+            data,
+            loop_kind: LoopKind::None,
+            loop_start: 0,
+            loop_end: 0,
+            volume,
+            finetune,
+            relative_note: 0,
+            panning: None,
+        };
+        // Loops of a single word mark samples which don't loop
+        let loop_kind = if loop_length > 2 { LoopKind::Forward } else { LoopKind::None };
+        Instrument::single(sample.looped(loop_kind, loop_start, loop_length))
+    }).collect();
+
+    let orders: Vec<usize> = order_table[..song_length].iter().map(|&pattern| pattern as usize).collect();
+    Ok(Module {
+        name,
+        channels,
+        restart_position: if restart_position < orders.len() { restart_position } else { 0 },
+        orders,
+        patterns,
+        instruments,
+        speed: 6,
+        tempo: 125,
+        frequency_mode: FrequencyMode::Amiga,
+        // Amiga channels alternate left, right, right, left
+        panning: (0..channels).map(|channel| if matches!(channel % 4, 0 | 3) { 0.25 } else { 0.75 }).collect(),
+    })
+}
+
+/// Reads the channel count from the tag at offset 1080, such as `M.K.` or `8CHN`.
+fn channel_count(tag: &[u8]) -> Option<usize> {
+    let count = match tag {
+        b"M.K." | b"M!K!" | b"FLT4" | b"4CHN" => 4,
+        b"FLT8" | b"OKTA" | b"CD81" => 8,
+        [digit, b'C', b'H', b'N'] if digit.is_ascii_digit() => (digit - b'0') as usize,
+        [tens, ones, b'C', b'H'] if tens.is_ascii_digit() && ones.is_ascii_digit() => ((tens - b'0') * 10 + (ones - b'0')) as usize,
+        _ => return None,
+    };
+    Some(count).filter(|&count| count > 0 && count <= MAX_CHANNELS)
+}
+
+/// Converts an Amiga period to a note, where C-2 (period 428) plays like C-4 in an XM.
+fn note_from_period(period: u16) -> u8 {
+    let note = 48.0 + 12.0 * (428.0 / period as f32).log2();
+    note.round().clamp(0.0, 95.0) as u8
+}
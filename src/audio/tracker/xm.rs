@@ -0,0 +1,199 @@
+//! Loads FastTracker II XM files.
+
+use std::io;
+
+use super::{invalid_data, Cell, Envelope, FrequencyMode, Instrument, LoopKind, Module, Note, Pattern, Reader, Sample, MAX_CHANNELS};
+
+pub(super) const SIGNATURE: &[u8] = b"Extended Module: ";
+
+const KEY_OFF: u8 = 97;
+
+pub(super) fn parse(data: &[u8]) -> io::Result<Module> {
+    let mut reader = Reader::new(data);
+    reader.skip(SIGNATURE.len());
+    let name = reader.text(20)?;
+    // 0x1A, tracker name and version
+    reader.skip(1 + 20 + 2);
+
+    let header_start = reader.position();
+    let header_size = reader.u32_le()? as usize;
+    let song_length = (reader.u16_le()? as usize).min(256);
+    let restart_position = reader.u16_le()? as usize;
+    let channels = reader.u16_le()? as usize;
+    let pattern_count = reader.u16_le()? as usize;
+    let instrument_count = reader.u16_le()? as usize;
+    let flags = reader.u16_le()?;
+    let speed = reader.u16_le()? as u32;
+    let tempo = reader.u16_le()? as u32;
+    let orders: Vec<usize> = reader.bytes(256)?[..song_length].iter().map(|&pattern| pattern as usize).collect();
+
+    if channels == 0 || channels > MAX_CHANNELS {
+        return Err(invalid_data("unsupported number of channels"));
+    }
+
+    reader.seek(header_start + header_size);
+    let mut patterns = Vec::with_capacity(pattern_count);
+    for _ in 0..pattern_count {
+        let start = reader.position();
+        let header_length = reader.u32_le()? as usize;
+        // Packing type, always 0
+        reader.skip(1);
+        let rows = reader.u16_le()? as usize;
+        let packed_size = reader.u16_le()? as usize;
+        reader.seek(start + header_length);
+        patterns.push(unpack_pattern(reader.bytes(packed_size)?, rows, channels)?);
+    }
+
+    let mut instruments = Vec::with_capacity(instrument_count);
+    for _ in 0..instrument_count {
+        instruments.push(parse_instrument(&mut reader)?);
+    }
+
+    Ok(Module {
+        name,
+        channels,
+        restart_position: if restart_position < orders.len() { restart_position } else { 0 },
+        orders,
+        patterns,
+        instruments,
+        speed,
+        tempo,
+        frequency_mode: if flags & 1 != 0 { FrequencyMode::Linear } else { FrequencyMode::Amiga },
+        panning: vec![0.5; channels],
+    })
+}
+
+/// Unpacks a pattern, where each cell starts with a byte flagging which of its fields follow.
+fn unpack_pattern(packed: &[u8], rows: usize, channels: usize) -> io::Result<Pattern> {
+    let mut reader = Reader::new(packed);
+    let mut cells = vec![Cell::default(); rows * channels];
+
+    for cell in &mut cells {
+        if reader.is_empty() {
+            break;
+        }
+
+        let first = reader.u8()?;
+        let (note, fields) = if first & 0x80 != 0 {
+            let note = if first & 0x01 != 0 { reader.u8()? } else { 0 };
+            (note, first)
+        } else {
+            // Without the flag the byte is the note, and all other fields follow
+            (first, 0x1E)
+        };
+
+        let mut field = |flag: u8| if fields & flag != 0 { reader.u8() } else { Ok(0) };
+        *cell = Cell {
+            note: match note {
+                1..=96 => Note::On(note - 1),
+                KEY_OFF => Note::Off,
+                _ => Note::None,
+            },
+            instrument: field(0x02)?,
+            volume: field(0x04)?,
+            effect: field(0x08)?,
+            param: field(0x10)?,
+        };
+    }
+
+    Ok(Pattern { rows, cells })
+}
+
+fn parse_instrument(reader: &mut Reader) -> io::Result<Instrument> {
+    let start = reader.position();
+    let header_size = reader.u32_le()? as usize;
+    // Name and type
+    reader.skip(22 + 1);
+    let sample_count = reader.u16_le()? as usize;
+
+    if sample_count == 0 {
+        reader.seek(start + header_size);
+        return Ok(Instrument { samples: Vec::new(), keymap: [0; 96], volume_envelope: None, fadeout: 0 });
+    }
+
+    let sample_header_size = reader.u32_le()? as usize;
+    let mut keymap = [0; 96];
+    keymap.copy_from_slice(reader.bytes(96)?);
+    let envelope_points = reader.bytes(48)?;
+    // Panning envelope
+    reader.skip(48);
+    let point_count = (reader.u8()? as usize).min(12);
+    reader.skip(1);
+    let sustain = reader.u8()? as usize;
+    let loop_start = reader.u8()? as usize;
+    let loop_end = reader.u8()? as usize;
+    reader.skip(3);
+    let envelope_flags = reader.u8()?;
+    // Panning envelope flags and auto-vibrato
+    reader.skip(1 + 4);
+    let fadeout = reader.u16_le()?;
+    reader.seek(start + header_size);
+
+    let volume_envelope = (envelope_flags & 1 != 0 && point_count > 0).then(|| {
+        let points = envelope_points.chunks_exact(4).take(point_count)
+            .map(|point| (u16::from_le_bytes([point[0], point[1]]), u16::from_le_bytes([point[2], point[3]]).min(64) as u8))
+            .collect();
+        Envelope {
+            points,
+            sustain: (envelope_flags & 2 != 0 && sustain < point_count).then_some(sustain),
+            loop_points: (envelope_flags & 4 != 0 && loop_start <= loop_end && loop_end < point_count).then_some((loop_start, loop_end)),
+        }
+    });
+
+    let mut headers = Vec::with_capacity(sample_count);
+    for _ in 0..sample_count {
+        let sample_start = reader.position();
+        let length = reader.u32_le()? as usize;
+        let loop_start = reader.u32_le()? as usize;
+        let loop_length = reader.u32_le()? as usize;
+        let volume = reader.u8()?.min(64);
+        let finetune = reader.u8()? as i8;
+        let sample_type = reader.u8()?;
+        let panning = reader.u8()?;
+        let relative_note = reader.u8()? as i8;
+        reader.seek(sample_start + sample_header_size);
+        headers.push((length, loop_start, loop_length, volume, finetune, sample_type, panning, relative_note));
+    }
+
+    let samples = headers.into_iter().map(|(length, loop_start, loop_length, volume, finetune, sample_type, panning, relative_note)| {
+        let sixteen_bit = sample_type & 0x10 != 0;
+        let bytes = reader.bytes_truncated(length);
+        let sample = Sample {
+            data: decode_deltas(bytes, sixteen_bit),
+            loop_kind: LoopKind::None,
+            loop_start: 0,
+            loop_end: 0,
+            volume,
+            finetune,
+            relative_note,
+            panning: Some(panning as f32 / 255.0),
+        };
+        let loop_kind = match sample_type & 0x03 {
+            1 => LoopKind::Forward,
+            2 => LoopKind::PingPong,
+            _ => LoopKind::None,
+        };
+        // Lengths are in bytes, so twice the frames of 16-bit samples
+        let frame_size = if sixteen_bit { 2 } else { 1 };
+        sample.looped(loop_kind, loop_start / frame_size, loop_length / frame_size)
+    }).collect();
+
+    Ok(Instrument { samples, keymap, volume_envelope, fadeout })
+}
+
+/// Samples are stored as the difference to the previous sample.
+fn decode_deltas(bytes: &[u8], sixteen_bit: bool) -> Vec<f32> {
+    if sixteen_bit {
+        let mut value = 0i16;
+        bytes.chunks_exact(2).map(|delta| {
+            value = value.wrapping_add(i16::from_le_bytes([delta[0], delta[1]]));
+            value as f32 / 32768.0
+        }).collect()
+    } else {
+        let mut value = 0i8;
+        bytes.iter().map(|&delta| {
+            value = value.wrapping_add(delta as i8);
+            value as f32 / 128.0
+        }).collect()
+    }
+}
@@ -0,0 +1,358 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+const LOG_ENV_VAR: &str = "PONDI_LOG";
+const LOG_FILE_ENV_VAR: &str = "PONDI_LOG_FILE";
+const DEFAULT_MAX_FILE_SIZE: u64 = 1024 * 1024; // 1 MiB
+const DEFAULT_MAX_FILES: usize = 3;
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// The subsystem a diagnostic message originates from. Each category has its own level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Assets,
+    Physics,
+    Input,
+    Audio,
+    Game,
+}
+
+impl Category {
+    const ALL: [Category; 5] = [Category::Assets, Category::Physics, Category::Input, Category::Audio, Category::Game];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Assets => "assets",
+            Category::Physics => "physics",
+            Category::Input => "input",
+            Category::Audio => "audio",
+            Category::Game => "game",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|category| category.name() == name)
+    }
+}
+
+/// Severity of a diagnostic message, from most to least severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Off => "OFF",
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "off" => Some(Level::Off),
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+}
+
+/// Which messages are written, and where.
+///
+/// Levels are given as a filter spec such as `warn,physics=debug,assets=off`: a bare level
+/// sets the default for all categories, and `category=level` overrides a single category.
+pub struct Config {
+    levels: [Level; Category::ALL.len()],
+    pub console: bool,
+    pub file: Option<PathBuf>,
+    pub max_file_size: u64,
+    pub max_files: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            levels: [Level::Warn; Category::ALL.len()],
+            console: true,
+            file: None,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            max_files: DEFAULT_MAX_FILES,
+        }
+    }
+}
+
+impl Config {
+    /// Builds the configuration from the `PONDI_LOG` and `PONDI_LOG_FILE` environment
+    /// variables, overridden by the `--log <spec>` and `--log-file <path>` command line arguments.
+    pub fn from_env_and_args(args: impl IntoIterator<Item = String>) -> Self {
+        let mut config = Config::default();
+
+        if let Ok(spec) = std::env::var(LOG_ENV_VAR) {
+            config.apply_filter(&spec);
+        }
+        if let Ok(path) = std::env::var(LOG_FILE_ENV_VAR) {
+            config.file = Some(PathBuf::from(path));
+        }
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--log" => {
+                    if let Some(spec) = args.next() {
+                        config.apply_filter(&spec);
+                    }
+                }
+                "--log-file" => config.file = args.next().map(PathBuf::from),
+                _ => {}
+            }
+        }
+
+        config
+    }
+
+    /// Applies a filter spec on top of the current levels. Unknown entries are ignored.
+    pub fn apply_filter(&mut self, spec: &str) {
+        for directive in spec.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+            match directive.split_once('=') {
+                Some((category, level)) => {
+                    if let (Some(category), Some(level)) = (Category::parse(category.trim()), Level::parse(level.trim())) {
+                        self.levels[category as usize] = level;
+                    }
+                }
+                None => {
+                    if let Some(level) = Level::parse(directive) {
+                        self.levels = [level; Category::ALL.len()];
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn level(&self, category: Category) -> Level {
+        self.levels[category as usize]
+    }
+}
+
+/// A log file which is rotated to `<name>.1`, `<name>.2`, ... once it exceeds its maximum size.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_size: u64, max_files: usize) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { path, file, size, max_size, max_files })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        if self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        // Shift older files up by one, dropping the oldest
+        for index in (1..self.max_files).rev() {
+            let from = if index == 1 { self.path.clone() } else { self.rotated_path(index - 1) };
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(index))?;
+            }
+        }
+
+        self.file = OpenOptions::new().create(true).write(true).truncate(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+struct Logger {
+    config: Config,
+    file: Option<Mutex<RotatingFile>>,
+    start: Instant,
+}
+
+/// Installs the global diagnostics configuration. Only the first call has any effect.
+///
+/// Messages logged before `init` is called use the default configuration.
+pub fn init(config: Config) {
+    let mut open_error = None;
+    let file = config.file.clone().and_then(|path| {
+        match RotatingFile::open(path.clone(), config.max_file_size, config.max_files) {
+            Ok(file) => Some(Mutex::new(file)),
+            Err(e) => {
+                open_error = Some(format!("Failed to open log file {}: {}", path.display(), e));
+                None
+            }
+        }
+    });
+
+    let _ = LOGGER.set(Logger { config, file, start: Instant::now() });
+
+    if let Some(error) = open_error {
+        log_error!(Game, "{}", error);
+    }
+}
+
+fn logger() -> &'static Logger {
+    LOGGER.get_or_init(|| Logger { config: Config::default(), file: None, start: Instant::now() })
+}
+
+/// Returns true if messages of the given category and level would be written.
+pub fn enabled(category: Category, level: Level) -> bool {
+    level != Level::Off && level <= logger().config.level(category)
+}
+
+/// Writes a message if its category is enabled at the given level. Use the `log_*` macros
+/// instead of calling this directly.
+pub fn log(category: Category, level: Level, args: fmt::Arguments) {
+    if !enabled(category, level) {
+        return;
+    }
+
+    let logger = logger();
+    let elapsed = logger.start.elapsed().as_secs_f32();
+    let line = format!("[{:>9.3}s] {:<5} {}: {}\n", elapsed, level.name(), category.name(), args);
+
+    if logger.config.console {
+        if level <= Level::Warn {
+            eprint!("{}", line);
+        } else {
+            print!("{}", line);
+        }
+    }
+
+    if let Some(file) = &logger.file {
+        if let Ok(mut file) = file.lock() {
+            let _ = file.write_line(&line);
+        }
+    }
+}
+
+macro_rules! log_at {
+    ($level:ident, $category:ident, $($arg:tt)*) => {
+        $crate::diagnostics::log(
+            $crate::diagnostics::Category::$category,
+            $crate::diagnostics::Level::$level,
+            format_args!($($arg)*),
+        )
+    };
+}
+
+/// Logs an error, e.g. `log_error!(Audio, "Failed to decode {}", path)`.
+macro_rules! log_error { ($category:ident, $($arg:tt)*) => { $crate::diagnostics::log_at!(Error, $category, $($arg)*) }; }
+/// Logs a warning, e.g. `log_warn!(Assets, "Missing sprite {}", path)`.
+macro_rules! log_warn { ($category:ident, $($arg:tt)*) => { $crate::diagnostics::log_at!(Warn, $category, $($arg)*) }; }
+/// Logs an informational message, e.g. `log_info!(Assets, "Loaded {}", path)`.
+macro_rules! log_info { ($category:ident, $($arg:tt)*) => { $crate::diagnostics::log_at!(Info, $category, $($arg)*) }; }
+/// Logs a debug message, e.g. `log_debug!(Physics, "Box {} is falling", id)`.
+macro_rules! log_debug { ($category:ident, $($arg:tt)*) => { $crate::diagnostics::log_at!(Debug, $category, $($arg)*) }; }
+/// Logs a per-frame trace message, e.g. `log_trace!(Physics, "Checking obstacle {}", id)`.
+macro_rules! log_trace { ($category:ident, $($arg:tt)*) => { $crate::diagnostics::log_at!(Trace, $category, $($arg)*) }; }
+
+pub(crate) use {log_at, log_debug, log_error, log_info, log_trace, log_warn};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory for a test's log files.
+    fn log_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("age_of_panda_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn filter_sets_the_default_and_overrides_categories() {
+        let mut config = Config::default();
+        config.apply_filter("info, physics=trace,assets=OFF");
+        assert_eq!(config.level(Category::Game), Level::Info);
+        assert_eq!(config.level(Category::Physics), Level::Trace);
+        assert_eq!(config.level(Category::Assets), Level::Off);
+
+        // A later bare level resets every category
+        config.apply_filter("error");
+        assert_eq!(config.level(Category::Physics), Level::Error);
+    }
+
+    #[test]
+    fn filter_ignores_unknown_entries() {
+        let mut config = Config::default();
+        config.apply_filter("loud,graphics=debug,audio=verbose,,audio=debug");
+        assert_eq!(config.level(Category::Game), Level::Warn);
+        assert_eq!(config.level(Category::Audio), Level::Debug);
+    }
+
+    #[test]
+    fn arguments_set_the_filter_and_file() {
+        let args = ["--audio", "null", "--log", "debug", "--log-file", "game.log"].map(String::from);
+        let config = Config::from_env_and_args(args);
+        assert_eq!(config.level(Category::Input), Level::Debug);
+        assert_eq!(config.file, Some(PathBuf::from("game.log")));
+    }
+
+    #[test]
+    fn full_files_rotate_and_the_oldest_is_dropped() {
+        let dir = log_dir("rotation");
+        let path = dir.join("game.log");
+        let mut file = RotatingFile::open(path.clone(), 10, 3).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write_line(line).unwrap();
+        }
+
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("game.log"), "fourth\n");
+        assert_eq!(read("game.log.1"), "third\n");
+        assert_eq!(read("game.log.2"), "second\n");
+        assert!(!dir.join("game.log.3").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reopened_files_keep_their_size() {
+        let dir = log_dir("reopen");
+        let path = dir.join("game.log");
+        RotatingFile::open(path.clone(), 10, 2).unwrap().write_line("12345\n").unwrap();
+
+        let mut file = RotatingFile::open(path.clone(), 10, 2).unwrap();
+        file.write_line("67890\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "67890\n");
+        assert_eq!(fs::read_to_string(dir.join("game.log.1")).unwrap(), "12345\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
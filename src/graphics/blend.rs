@@ -0,0 +1,124 @@
+/// Blend modes used when compositing a source pixel onto a destination pixel.
+///
+/// All modes respect the source alpha channel: the blended result is mixed with the
+/// destination by the source alpha, so a half-transparent additive glow only adds half
+/// of its light.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Straight alpha-over.
+    Alpha,
+    /// Adds the source colour to the destination, useful for glows and explosions.
+    Additive,
+    /// Multiplies the source and destination colours, useful for shadows and tinting.
+    Multiply,
+    /// Inverse multiply, brightens the destination without blowing out like additive.
+    Screen,
+}
+
+/// Blends an ARGB `src` pixel onto an ARGB `dst` pixel using the given blend mode.
+///
+/// The returned pixel always has full alpha, as the window buffer is opaque.
+pub fn blend_pixel(dst: u32, src: u32, mode: BlendMode) -> u32 {
+    let alpha = (src >> 24) & 0xFF;
+    if alpha == 0 {
+        return dst;
+    }
+
+    let mut blended = 0xFF000000;
+    for shift in [16, 8, 0] {
+        let s = (src >> shift) & 0xFF;
+        let d = (dst >> shift) & 0xFF;
+
+        // Colour produced by the blend mode before alpha is taken into account
+        let mixed = match mode {
+            BlendMode::Alpha => s,
+            BlendMode::Additive => (s + d).min(255),
+            BlendMode::Multiply => s * d / 255,
+            BlendMode::Screen => 255 - (255 - s) * (255 - d) / 255,
+        };
+
+        let channel = (mixed * alpha + d * (255 - alpha)) / 255;
+        blended |= (channel & 0xFF) << shift;
+    }
+
+    blended
+}
+
+/// Scales the alpha channel of an ARGB pixel by `opacity` (0.0 - 1.0).
+pub fn apply_opacity(pixel: u32, opacity: f32) -> u32 {
+    let alpha = (pixel >> 24) & 0xFF;
+    let scaled = (alpha as f32 * opacity.clamp(0.0, 1.0)).round() as u32;
+    (scaled << 24) | (pixel & 0x00FFFFFF)
+}
+
+/// Linearly interpolates each ARGB channel from `from` to `to`, with `t` from 0.0 to 1.0.
+pub fn lerp_color(from: u32, to: u32, t: f32) -> u32 {
+    let t = t.clamp(0.0, 1.0);
+    let mut color = 0;
+    for shift in [24, 16, 8, 0] {
+        let a = ((from >> shift) & 0xFF) as f32;
+        let b = ((to >> shift) & 0xFF) as f32;
+        color |= ((a + (b - a) * t).round() as u32) << shift;
+    }
+    color
+}
+
+/// Multiplies each ARGB channel of a pixel with the corresponding channel of the tint colour.
+pub fn tint(pixel: u32, color: u32) -> u32 {
+    let mut tinted = 0;
+    for shift in [24, 16, 8, 0] {
+        let channel = ((pixel >> shift) & 0xFF) * ((color >> shift) & 0xFF) / 255;
+        tinted |= channel << shift;
+    }
+    tinted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GREY: u32 = 0xFF808080;
+
+    #[test]
+    fn transparent_pixels_leave_the_destination_alone() {
+        for mode in [BlendMode::Alpha, BlendMode::Additive, BlendMode::Multiply, BlendMode::Screen] {
+            assert_eq!(blend_pixel(0x00123456, 0x00FFFFFF, mode), 0x00123456);
+        }
+    }
+
+    #[test]
+    fn opaque_pixels_blend_by_mode() {
+        assert_eq!(blend_pixel(GREY, 0xFF204060, BlendMode::Alpha), 0xFF204060);
+        assert_eq!(blend_pixel(GREY, 0xFF204090, BlendMode::Additive), 0xFFA0C0FF);
+        assert_eq!(blend_pixel(GREY, 0xFFFF0080, BlendMode::Multiply), 0xFF800040);
+        assert_eq!(blend_pixel(GREY, 0xFFFF0000, BlendMode::Screen), 0xFFFF8080);
+    }
+
+    #[test]
+    fn source_alpha_mixes_the_blend_with_the_destination() {
+        // Half an additive white only adds half its light
+        assert_eq!(blend_pixel(0xFF000000, 0x80FFFFFF, BlendMode::Additive), 0xFF808080);
+        assert_eq!(blend_pixel(0xFF000000, 0x80FF0000, BlendMode::Alpha), 0xFF800000);
+    }
+
+    #[test]
+    fn opacity_scales_only_alpha() {
+        assert_eq!(apply_opacity(0xFF123456, 0.5), 0x80123456);
+        assert_eq!(apply_opacity(0xFF123456, 2.0), 0xFF123456);
+        assert_eq!(apply_opacity(0xFF123456, -1.0), 0x00123456);
+    }
+
+    #[test]
+    fn lerp_color_interpolates_every_channel() {
+        assert_eq!(lerp_color(0x00000000, 0xFF804020, 0.0), 0x00000000);
+        assert_eq!(lerp_color(0x00000000, 0xFF804020, 0.5), 0x80402010);
+        assert_eq!(lerp_color(0x00000000, 0xFF804020, 1.0), 0xFF804020);
+        assert_eq!(lerp_color(0x00000000, 0xFF804020, 3.0), 0xFF804020);
+    }
+
+    #[test]
+    fn tint_multiplies_channels() {
+        assert_eq!(tint(0xFFFFFFFF, 0xFF8040C0), 0xFF8040C0);
+        assert_eq!(tint(0x80FF8000, 0xFF808080), 0x80804000);
+    }
+}
@@ -0,0 +1,111 @@
+use crate::graphics::blend::BlendMode;
+use crate::graphics::font::TextStyle;
+use crate::graphics::primitives::Shape;
+use crate::graphics::render_queue::{Layer, RenderQueue};
+use crate::state::collision::Aabb;
+use crate::state::input_logic::collision_probe;
+use crate::state::GameState;
+
+const OBSTACLE_COLOR: u32 = 0xFF00FF00;
+const SUPPORTING_OBSTACLE_COLOR: u32 = 0xFF0080FF;
+const PLAYER_COLOR: u32 = 0xFFFFFF00;
+const COLLISION_PROBE_COLOR: u32 = 0xFFFF0000;
+const CONTACT_COLOR: u32 = 0xFF00FFFF;
+const VELOCITY_COLOR: u32 = 0xFFFF00FF;
+// Length of the velocity arrow per pixel per frame of velocity
+const VELOCITY_ARROW_SCALE: f32 = 6.0;
+const VELOCITY_ARROW_HEAD: f32 = 4.0;
+const PANEL_COLOR: u32 = 0xA0000000;
+const TEXT_COLOR: u32 = 0xFFFFFFFF;
+
+/// Queues the debug overlay, toggled with F3, on top of everything else.
+///
+/// Shows obstacle hitboxes, highlighting the ones the player stands on, the player's hitbox, contacts and velocity, the probe boxes
+/// used by `check_collision`, and a panel with the player's state and timing.
+pub fn queue_debug_overlay<'s>(game_state: &'s GameState, render_queue: &mut RenderQueue<'s>) {
+    if !game_state.debug_overlay {
+        return;
+    }
+
+    let player = &game_state.player;
+
+    for (id, obstacle) in game_state.all_maps[game_state.current_map_index].obstacles.iter() {
+        let color = if player.on_obstacles.contains(&id) { SUPPORTING_OBSTACLE_COLOR } else { OBSTACLE_COLOR };
+        render_queue.push_shape(Layer::Debug, 0, outline(&obstacle.aabb()), color, BlendMode::Alpha);
+    }
+
+    let hitbox = player.hitbox.at(player.x, player.y);
+    render_queue.push_shape(Layer::Debug, 0, outline(&hitbox), PLAYER_COLOR, BlendMode::Alpha);
+
+    // check_collision tests these boxes on either side of the player
+    for is_left in [true, false] {
+        render_queue.push_shape(Layer::Debug, 1, outline(&collision_probe(player, is_left)), COLLISION_PROBE_COLOR, BlendMode::Alpha);
+    }
+
+    // Highlight the sides of the hitbox which touch something solid
+    let (left, right, top, bottom) = (hitbox.left() as i32, hitbox.right() as i32 - 1, hitbox.top() as i32, hitbox.bottom() as i32 - 1);
+    let contact_edges = [
+        (player.contacts.ground, (left, bottom), (right, bottom)),
+        (player.contacts.ceiling, (left, top), (right, top)),
+        (player.contacts.wall_left, (left, top), (left, bottom)),
+        (player.contacts.wall_right, (right, top), (right, bottom)),
+    ];
+    for (_, from, to) in contact_edges.into_iter().filter(|(touching, _, _)| *touching) {
+        render_queue.push_shape(Layer::Debug, 2, Shape::Line { from, to }, CONTACT_COLOR, BlendMode::Alpha);
+    }
+
+    // The player's velocity, as an arrow from the centre of the hitbox
+    let (center_x, center_y) = hitbox.center();
+    let speed = player.vx.hypot(player.vy);
+    if speed > 0.0 {
+        let (dir_x, dir_y) = (player.vx / speed, player.vy / speed);
+        let length = speed * VELOCITY_ARROW_SCALE;
+        let (tip_x, tip_y) = (center_x + dir_x * length, center_y + dir_y * length);
+        let (base_x, base_y) = (tip_x - dir_x * VELOCITY_ARROW_HEAD, tip_y - dir_y * VELOCITY_ARROW_HEAD);
+
+        let shaft = Shape::Line { from: (center_x as i32, center_y as i32), to: (base_x as i32, base_y as i32) };
+        render_queue.push_shape(Layer::Debug, 2, shaft, VELOCITY_COLOR, BlendMode::Alpha);
+
+        // Triangular head, its base perpendicular to the velocity
+        let head = Shape::Polygon {
+            points: vec![
+                (tip_x as i32, tip_y as i32),
+                ((base_x - dir_y * VELOCITY_ARROW_HEAD) as i32, (base_y + dir_x * VELOCITY_ARROW_HEAD) as i32),
+                ((base_x + dir_y * VELOCITY_ARROW_HEAD) as i32, (base_y - dir_x * VELOCITY_ARROW_HEAD) as i32),
+            ],
+            filled: true,
+        };
+        render_queue.push_shape(Layer::Debug, 2, head, VELOCITY_COLOR, BlendMode::Alpha);
+    }
+
+    // Text panel with the player's state and frame timing
+    let frame_ms = game_state.frame_time.as_secs_f32() * 1000.0;
+    let fps = if frame_ms > 0.0 { 1000.0 / frame_ms } else { 0.0 };
+    let lines = [
+        format!("STATE: {:?}", player.state),
+        format!("POS: {:.1}, {:.1}", player.x, player.y),
+        format!("VEL: {:.2}, {:.2}", player.vx, player.vy),
+        format!("FRAME: {:.2} MS ({:.0} FPS)", frame_ms, fps),
+    ];
+
+    let font = &game_state.font;
+    let style = TextStyle::new(TEXT_COLOR);
+    let line_step = font.line_height + style.line_spacing;
+    let panel_width = lines.iter().map(|line| font.measure(line)).max().unwrap_or(0) + 4;
+    let panel_height = lines.len() as i32 * line_step + 3;
+
+    let panel = Shape::Rect { position: (0, 0), size: (panel_width, panel_height), filled: true };
+    render_queue.push_shape(Layer::Debug, 3, panel, PANEL_COLOR, BlendMode::Alpha);
+
+    for (index, line) in lines.into_iter().enumerate() {
+        render_queue.push_text(Layer::Debug, 4, 2, 2 + index as i32 * line_step, line, font, style);
+    }
+}
+
+fn outline(aabb: &Aabb) -> Shape {
+    Shape::Rect {
+        position: (aabb.x as i32, aabb.y as i32),
+        size: (aabb.width as i32, aabb.height as i32),
+        filled: false,
+    }
+}
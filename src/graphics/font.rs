@@ -0,0 +1,593 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use image::GenericImageView;
+
+use crate::graphics::blend::{blend_pixel, tint, BlendMode};
+use crate::graphics::sprites::img_to_buffer;
+
+/// Cell size and characters of the glyph sheets `BitmapFont::load` reads: printable ASCII
+/// from the space on, 8x8 pixels each.
+const ASCII_SHEET_GLYPH_SIZE: usize = 8;
+const ASCII_SHEET_CHARACTERS: &str = " !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+
+const BUILTIN_GLYPH_WIDTH: usize = 5;
+const BUILTIN_GLYPH_HEIGHT: usize = 7;
+
+/// Glyphs of the built-in 5x7 font, one `u8` per row with the leftmost pixel in bit 4.
+/// Lowercase letters are drawn with their uppercase glyph.
+const BUILTIN_GLYPHS: &[(char, [u8; BUILTIN_GLYPH_HEIGHT])] = &[
+    (' ', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('A', [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('B', [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110]),
+    ('C', [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110]),
+    ('D', [0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110]),
+    ('E', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111]),
+    ('F', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('G', [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111]),
+    ('H', [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('I', [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('J', [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100]),
+    ('K', [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001]),
+    ('L', [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111]),
+    ('M', [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001]),
+    ('N', [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001]),
+    ('O', [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('P', [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('Q', [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101]),
+    ('R', [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001]),
+    ('S', [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110]),
+    ('T', [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+    ('U', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('V', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100]),
+    ('W', [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010]),
+    ('X', [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001]),
+    ('Y', [0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100, 0b00100]),
+    ('Z', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111]),
+    ('0', [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110]),
+    ('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('2', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+    ('3', [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+    ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+    ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+    ('6', [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+    ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+    ('8', [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+    ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+    ('.', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100]),
+    (',', [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000]),
+    (':', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000]),
+    (';', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b00100, 0b01000]),
+    ('!', [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100]),
+    ('?', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100]),
+    ('-', [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000]),
+    ('+', [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000]),
+    ('=', [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000]),
+    ('/', [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000]),
+    ('(', [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010]),
+    (')', [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000]),
+    ('%', [0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011]),
+    ('\'', [0b00100, 0b00100, 0b01000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('"', [0b01010, 0b01010, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('<', [0b00010, 0b00100, 0b01000, 0b10000, 0b01000, 0b00100, 0b00010]),
+    ('>', [0b01000, 0b00100, 0b00010, 0b00001, 0b00010, 0b00100, 0b01000]),
+    ('_', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111]),
+    ('#', [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010]),
+    ('*', [0b00000, 0b00100, 0b10101, 0b01110, 0b10101, 0b00100, 0b00000]),
+    ('[', [0b01110, 0b01000, 0b01000, 0b01000, 0b01000, 0b01000, 0b01110]),
+    (']', [0b01110, 0b00010, 0b00010, 0b00010, 0b00010, 0b00010, 0b01110]),
+    ('|', [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+];
+
+/// Horizontal alignment of each line of text relative to the `x` coordinate passed to `draw_text`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// How text is laid out and coloured when drawn.
+#[derive(Debug, Clone, Copy)]
+pub struct TextStyle {
+    /// ARGB colour multiplied with the glyph pixels. White glyphs take on this colour exactly.
+    pub color: u32,
+    pub align: Align,
+    /// Lines longer than this many pixels are word wrapped.
+    pub max_width: Option<i32>,
+    /// Extra pixels between lines on top of the font's line height.
+    pub line_spacing: i32,
+}
+
+impl TextStyle {
+    pub fn new(color: u32) -> Self {
+        Self {
+            color,
+            align: Align::Left,
+            max_width: None,
+            line_spacing: 1,
+        }
+    }
+
+    pub fn align(mut self, align: Align) -> Self {
+        self.align = align;
+        self
+    }
+
+    pub fn max_width(mut self, max_width: i32) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+}
+
+/// Location and metrics of a single glyph on the font's page image.
+#[derive(Debug, Clone, Copy)]
+pub struct Glyph {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub x_offset: i32,
+    pub y_offset: i32,
+    pub x_advance: i32,
+}
+
+/// A bitmap font, consisting of a single page image holding all glyphs and their metrics.
+pub struct BitmapFont {
+    page: Vec<u32>,
+    page_width: usize,
+    glyphs: HashMap<char, Glyph>,
+    kerning: HashMap<(char, char), i32>,
+    pub line_height: i32,
+}
+
+impl BitmapFont {
+    /// Creates the built-in 5x7 font, which needs no assets and is always available.
+    pub fn builtin() -> Self {
+        let page_width = BUILTIN_GLYPHS.len() * BUILTIN_GLYPH_WIDTH;
+        let mut page = vec![0; page_width * BUILTIN_GLYPH_HEIGHT];
+        let mut glyphs = HashMap::new();
+
+        for (index, (character, rows)) in BUILTIN_GLYPHS.iter().enumerate() {
+            let glyph_x = index * BUILTIN_GLYPH_WIDTH;
+            for (row, bits) in rows.iter().enumerate() {
+                for col in 0..BUILTIN_GLYPH_WIDTH {
+                    if bits & (1 << (BUILTIN_GLYPH_WIDTH - 1 - col)) != 0 {
+                        page[row * page_width + glyph_x + col] = 0xFFFFFFFF;
+                    }
+                }
+            }
+
+            glyphs.insert(*character, Glyph {
+                x: glyph_x,
+                y: 0,
+                width: BUILTIN_GLYPH_WIDTH,
+                height: BUILTIN_GLYPH_HEIGHT,
+                x_offset: 0,
+                y_offset: 0,
+                x_advance: BUILTIN_GLYPH_WIDTH as i32 + 1,
+            });
+        }
+
+        Self {
+            page,
+            page_width,
+            glyphs,
+            kerning: HashMap::new(),
+            line_height: BUILTIN_GLYPH_HEIGHT as i32,
+        }
+    }
+
+    /// Loads a font from a BMFont descriptor if the path ends in `.fnt`, and from a variable
+    /// width glyph sheet of printable ASCII in 8x8 cells otherwise.
+    pub fn load(path: &str) -> io::Result<Self> {
+        if Path::new(path).extension().is_some_and(|extension| extension == "fnt") {
+            Self::from_bmfont(path)
+        } else {
+            Self::from_glyph_sheet(path, ASCII_SHEET_GLYPH_SIZE, ASCII_SHEET_GLYPH_SIZE, ASCII_SHEET_CHARACTERS, true)
+        }
+    }
+
+    /// Loads a font from a glyph sheet, an image where glyphs are laid out on a grid of
+    /// equally sized cells, left to right and top to bottom, in the order of `characters`.
+    ///
+    /// # Parameters
+    /// - `path`: Path to the glyph sheet image.
+    /// - `glyph_width`: The width of each cell in pixels.
+    /// - `glyph_height`: The height of each cell in pixels.
+    /// - `characters`: The characters on the sheet, in the order they appear.
+    /// - `variable_width`: If true, each glyph advances by its widest non-transparent column
+    ///   instead of the full cell width, and spaces advance by half a cell.
+    pub fn from_glyph_sheet(path: &str, glyph_width: usize, glyph_height: usize, characters: &str, variable_width: bool) -> io::Result<Self> {
+        let sheet = image::open(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let (page_width, page_height) = sheet.dimensions();
+        let (page_width, page_height) = (page_width as usize, page_height as usize);
+        let page = img_to_buffer(&sheet);
+
+        if glyph_width == 0 || glyph_height == 0 || glyph_width > page_width || glyph_height > page_height {
+            return Err(invalid_data(format!("{}x{} glyphs don't fit on the {}x{} glyph sheet {}", glyph_width, glyph_height, page_width, page_height, path)));
+        }
+
+        // Partial cells at the right and bottom edges are ignored
+        let columns = page_width / glyph_width;
+        let capacity = columns * (page_height / glyph_height);
+        let character_count = characters.chars().count();
+        if character_count > capacity {
+            return Err(invalid_data(format!("Glyph sheet {} holds {} glyphs, but {} characters were given", path, capacity, character_count)));
+        }
+
+        let mut glyphs = HashMap::new();
+        for (index, character) in characters.chars().enumerate() {
+            let x = (index % columns) * glyph_width;
+            let y = (index / columns) * glyph_height;
+
+            let x_advance = if !variable_width {
+                glyph_width as i32
+            } else if character == ' ' {
+                (glyph_width / 2) as i32
+            } else {
+                // Advance past the rightmost column containing a visible pixel
+                let used_width = (0..glyph_width)
+                    .rev()
+                    .find(|col| (0..glyph_height).any(|row| page[(y + row) * page_width + x + col] >> 24 != 0))
+                    .map_or(0, |col| col + 1);
+                used_width as i32 + 1
+            };
+
+            glyphs.insert(character, Glyph { x, y, width: glyph_width, height: glyph_height, x_offset: 0, y_offset: 0, x_advance });
+        }
+
+        Ok(Self {
+            page,
+            page_width,
+            glyphs,
+            kerning: HashMap::new(),
+            line_height: glyph_height as i32,
+        })
+    }
+
+    /// Loads a font from an AngelCode BMFont text descriptor (`.fnt`).
+    ///
+    /// Only single page fonts are supported. The page image is resolved relative to the
+    /// descriptor's directory.
+    pub fn from_bmfont(path: &str) -> io::Result<Self> {
+        let descriptor = fs::read_to_string(path)?;
+        let mut glyphs = HashMap::new();
+        let mut kerning = HashMap::new();
+        let mut line_height = 0;
+        let mut page_file = None;
+
+        for line in descriptor.lines() {
+            let mut tokens = line.split_whitespace();
+            let tag = tokens.next().unwrap_or("");
+            let attributes = parse_bmfont_attributes(line);
+            let number = |key: &str| attributes.get(key).and_then(|value| value.parse::<i32>().ok()).unwrap_or(0);
+
+            match tag {
+                "common" => line_height = number("lineHeight"),
+                "page" if number("id") == 0 => page_file = attributes.get("file").cloned(),
+                "char" => {
+                    if let Some(character) = char::from_u32(number("id") as u32) {
+                        glyphs.insert(character, Glyph {
+                            x: number("x") as usize,
+                            y: number("y") as usize,
+                            width: number("width") as usize,
+                            height: number("height") as usize,
+                            x_offset: number("xoffset"),
+                            y_offset: number("yoffset"),
+                            x_advance: number("xadvance"),
+                        });
+                    }
+                }
+                "kerning" => {
+                    let first = char::from_u32(number("first") as u32);
+                    let second = char::from_u32(number("second") as u32);
+                    if let (Some(first), Some(second)) = (first, second) {
+                        kerning.insert((first, second), number("amount"));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let page_file = page_file.ok_or_else(|| invalid_data(format!("No page defined in {}", path)))?;
+        let page_path = Path::new(path).parent().unwrap_or(Path::new("")).join(page_file);
+        let page_image = image::open(&page_path).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let (page_width, page_height) = page_image.dimensions();
+        let (page_width, page_height) = (page_width as usize, page_height as usize);
+
+        // Glyphs reaching past the page would draw pixels of the next row instead
+        if let Some((character, _)) = glyphs.iter().find(|(_, glyph)| glyph.x + glyph.width > page_width || glyph.y + glyph.height > page_height) {
+            return Err(invalid_data(format!("Glyph {:?} in {} lies outside its {}x{} page", character, path, page_width, page_height)));
+        }
+
+        Ok(Self {
+            page_width,
+            page: img_to_buffer(&page_image),
+            glyphs,
+            kerning,
+            line_height,
+        })
+    }
+
+    /// Looks up the glyph for a character, falling back to the uppercase glyph if needed.
+    fn glyph(&self, character: char) -> Option<&Glyph> {
+        self.glyphs.get(&character).or_else(|| self.glyphs.get(&character.to_ascii_uppercase()))
+    }
+
+    fn kerning(&self, previous: Option<char>, character: char) -> i32 {
+        previous.and_then(|previous| self.kerning.get(&(previous, character)).copied()).unwrap_or(0)
+    }
+
+    /// Returns the width in pixels of a single line of text.
+    pub fn measure(&self, text: &str) -> i32 {
+        let mut width = 0;
+        let mut previous = None;
+        for character in text.chars() {
+            if let Some(glyph) = self.glyph(character) {
+                width += glyph.x_advance + self.kerning(previous, character);
+            }
+            previous = Some(character);
+        }
+        width
+    }
+
+    /// Splits text into lines on newlines, word wrapping lines wider than `max_width`.
+    ///
+    /// Words which are wider than `max_width` on their own are broken between characters.
+    pub fn layout(&self, text: &str, max_width: Option<i32>) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        for paragraph in text.split('\n') {
+            let Some(max_width) = max_width else {
+                lines.push(paragraph.to_string());
+                continue;
+            };
+
+            let mut line = String::new();
+            for word in paragraph.split(' ') {
+                let candidate = if line.is_empty() { word.to_string() } else { format!("{} {}", line, word) };
+                if self.measure(&candidate) <= max_width {
+                    line = candidate;
+                    continue;
+                }
+
+                if !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+
+                // Break words which don't fit on a line by themselves
+                for character in word.chars() {
+                    line.push(character);
+                    if self.measure(&line) > max_width && line.chars().count() > 1 {
+                        line.pop();
+                        lines.push(std::mem::replace(&mut line, character.to_string()));
+                    }
+                }
+            }
+            lines.push(line);
+        }
+
+        lines
+    }
+
+    /// Draws text into the buffer with its first line's top edge at `y`.
+    ///
+    /// # Parameters
+    /// - `text`: The text to draw. Newlines start a new line.
+    /// - `x`: The left edge, centre or right edge of each line, depending on the alignment.
+    /// - `y`: The top edge of the first line.
+    /// - `style`: Colour, alignment and wrapping of the text.
+    /// - `buffer`: The buffer to draw into, using the same layout as the window buffer.
+    /// - `buffer_width`: The width of the buffer in pixels.
+    pub fn draw_text(&self, text: &str, x: i32, y: i32, style: &TextStyle, buffer: &mut [u32], buffer_width: usize) {
+        for (line_index, line) in self.layout(text, style.max_width).iter().enumerate() {
+            let line_width = self.measure(line);
+            let mut pen_x = match style.align {
+                Align::Left => x,
+                Align::Center => x - line_width / 2,
+                Align::Right => x - line_width,
+            };
+            let pen_y = y + line_index as i32 * (self.line_height + style.line_spacing);
+
+            let mut previous = None;
+            for character in line.chars() {
+                if let Some(glyph) = self.glyph(character) {
+                    pen_x += self.kerning(previous, character);
+                    self.draw_glyph(glyph, pen_x + glyph.x_offset, pen_y + glyph.y_offset, style.color, buffer, buffer_width);
+                    pen_x += glyph.x_advance;
+                }
+                previous = Some(character);
+            }
+        }
+    }
+
+    fn draw_glyph(&self, glyph: &Glyph, x: i32, y: i32, color: u32, buffer: &mut [u32], buffer_width: usize) {
+        let buffer_height = (buffer.len() / buffer_width.max(1)) as i32;
+
+        for row in 0..glyph.height {
+            let target_y = y + row as i32;
+            if target_y < 0 || target_y >= buffer_height {
+                continue;
+            }
+
+            for col in 0..glyph.width {
+                let target_x = x + col as i32;
+                if target_x < 0 || target_x >= buffer_width as i32 {
+                    continue;
+                }
+
+                let page_index = (glyph.y + row) * self.page_width + glyph.x + col;
+                let Some(&pixel) = self.page.get(page_index) else { continue };
+                let target_index = target_y as usize * buffer_width + target_x as usize;
+                buffer[target_index] = blend_pixel(buffer[target_index], tint(pixel, color), BlendMode::Alpha);
+            }
+        }
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Parses the `key=value` pairs of a BMFont descriptor line, stripping quotes from values.
+fn parse_bmfont_attributes(line: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut rest = line;
+
+    while let Some(equals) = rest.find('=') {
+        let key = rest[..equals].rsplit(char::is_whitespace).next().unwrap_or("").to_string();
+        let after = &rest[equals + 1..];
+
+        let (value, remaining) = if let Some(quoted) = after.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+        } else {
+            let end = after.find(char::is_whitespace).unwrap_or(after.len());
+            (&after[..end], &after[end..])
+        };
+
+        attributes.insert(key, value.to_string());
+        rest = remaining;
+    }
+
+    attributes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+
+    /// Creates an empty directory for the files of one test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("age_of_panda_font_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Saves an image whose pixels are opaque where `opaque(x, y)` holds.
+    fn save_image(path: &Path, width: u32, height: u32, opaque: impl Fn(u32, u32) -> bool) {
+        RgbaImage::from_fn(width, height, |x, y| if opaque(x, y) { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 0]) })
+            .save(path)
+            .unwrap();
+    }
+
+    #[test]
+    fn builtin_font_measures_advance_and_falls_back_to_uppercase() {
+        let font = BitmapFont::builtin();
+        assert_eq!(font.measure("AB"), 12);
+        assert_eq!(font.measure("ab"), font.measure("AB"));
+        // Characters without a glyph take no space
+        assert_eq!(font.measure("A\u{1F43C}B"), 12);
+    }
+
+    #[test]
+    fn layout_wraps_words_and_breaks_long_ones() {
+        let font = BitmapFont::builtin();
+        assert_eq!(font.layout("AB CD\nEF", None), vec!["AB CD", "EF"]);
+        assert_eq!(font.layout("AB CD EF", Some(30)), vec!["AB CD", "EF"]);
+        assert_eq!(font.layout("ABCDEFG", Some(24)), vec!["ABCD", "EFG"]);
+    }
+
+    #[test]
+    fn alignment_positions_lines_relative_to_x() {
+        let font = BitmapFont::builtin();
+        let width = 40;
+        let leftmost_column = |align: Align| {
+            let mut buffer = vec![0; width * 8];
+            font.draw_text("I", 20, 0, &TextStyle::new(0xFFFFFFFF).align(align), &mut buffer, width);
+            (0..width).find(|&x| (0..8).any(|y| buffer[y * width + x] != 0)).unwrap()
+        };
+
+        // The I glyph's stem is in its third column, and the glyph advances six pixels
+        assert_eq!(leftmost_column(Align::Left), 21);
+        assert_eq!(leftmost_column(Align::Center), 18);
+        assert_eq!(leftmost_column(Align::Right), 15);
+    }
+
+    #[test]
+    fn glyph_sheet_loads_cells_in_order_with_variable_width() {
+        let dir = test_dir("sheet");
+        let path = dir.join("sheet.png");
+        // Two 4x4 cells: A fills two columns of its cell, B fills three
+        save_image(&path, 8, 4, |x, _| x < 2 || (4..7).contains(&x));
+
+        let font = BitmapFont::from_glyph_sheet(path.to_str().unwrap(), 4, 4, "AB", true).unwrap();
+        assert_eq!(font.line_height, 4);
+        assert_eq!((font.glyphs[&'A'].x, font.glyphs[&'A'].x_advance), (0, 3));
+        assert_eq!((font.glyphs[&'B'].x, font.glyphs[&'B'].x_advance), (4, 4));
+
+        let fixed = BitmapFont::from_glyph_sheet(path.to_str().unwrap(), 4, 4, "AB", false).unwrap();
+        assert_eq!(fixed.measure("AB"), 8);
+    }
+
+    #[test]
+    fn glyph_sheet_rejects_glyphs_which_dont_fit() {
+        let dir = test_dir("sheet_errors");
+        let path = dir.join("sheet.png");
+        save_image(&path, 8, 4, |_, _| true);
+        let path = path.to_str().unwrap();
+
+        // Wider than the sheet
+        assert!(BitmapFont::from_glyph_sheet(path, 16, 4, "A", false).is_err());
+        // More characters than cells
+        assert!(BitmapFont::from_glyph_sheet(path, 4, 4, "ABC", false).is_err());
+        assert!(BitmapFont::from_glyph_sheet(path, 0, 4, "A", false).is_err());
+    }
+
+    #[test]
+    fn bmfont_loads_glyphs_kerning_and_page() {
+        let dir = test_dir("bmfont");
+        save_image(&dir.join("page.png"), 16, 8, |x, _| x < 12);
+        fs::write(dir.join("font.fnt"), "\
+info face=\"Test Font\" size=8
+common lineHeight=9 base=7 scaleW=16 scaleH=8 pages=1
+page id=0 file=\"page.png\"
+chars count=2
+char id=65 x=0 y=0 width=5 height=8 xoffset=0 yoffset=1 xadvance=6 page=0
+char id=86 x=6 y=0 width=5 height=8 xoffset=0 yoffset=1 xadvance=6 page=0
+kernings count=1
+kerning first=65 second=86 amount=-2
+").unwrap();
+
+        let font = BitmapFont::load(dir.join("font.fnt").to_str().unwrap()).unwrap();
+        assert_eq!(font.line_height, 9);
+        assert_eq!(font.page_width, 16);
+        assert_eq!((font.glyphs[&'V'].x, font.glyphs[&'V'].y_offset), (6, 1));
+        assert_eq!(font.measure("AV"), 10);
+        assert_eq!(font.measure("VA"), 12);
+    }
+
+    #[test]
+    fn bmfont_rejects_glyphs_outside_the_page_and_missing_pages() {
+        let dir = test_dir("bmfont_errors");
+        save_image(&dir.join("page.png"), 8, 8, |_, _| true);
+
+        fs::write(dir.join("outside.fnt"), "page id=0 file=\"page.png\"\nchar id=65 x=4 y=0 width=5 height=8 xadvance=6\n").unwrap();
+        assert!(BitmapFont::from_bmfont(dir.join("outside.fnt").to_str().unwrap()).is_err());
+
+        fs::write(dir.join("no_page.fnt"), "char id=65 x=0 y=0 width=5 height=8 xadvance=6\n").unwrap();
+        assert!(BitmapFont::from_bmfont(dir.join("no_page.fnt").to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn bmfont_attributes_keep_spaces_in_quoted_values() {
+        let attributes = parse_bmfont_attributes("info face=\"Age of Panda\" size=14 padding=0,0,0,0");
+        assert_eq!(attributes["face"], "Age of Panda");
+        assert_eq!(attributes["size"], "14");
+        assert_eq!(attributes["padding"], "0,0,0,0");
+    }
+
+    #[test]
+    fn shipped_title_font_loads() {
+        let font = BitmapFont::load("assets/fonts/title.fnt").unwrap();
+        assert!(font.measure("BLAST ZONE") > 0);
+    }
+}
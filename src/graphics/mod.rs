@@ -1,4 +1,5 @@
-pub mod sprites; pub mod renderer;
+pub mod sprites; pub mod renderer; pub mod blend; pub mod render_queue; pub mod surface; pub mod primitives; pub mod font; pub mod debug_overlay;
+pub mod particles;
 
 pub const SCALED_WINDOW_WIDTH: usize = 640;
 pub const SCALED_WINDOW_HEIGHT: usize = 480;
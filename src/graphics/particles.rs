@@ -0,0 +1,304 @@
+use std::f32::consts::PI;
+
+use crate::graphics::blend::{lerp_color, BlendMode};
+use crate::graphics::primitives::Shape;
+use crate::graphics::render_queue::{Layer, RenderQueue};
+use crate::graphics::sprites::{BoxSkin, Sprites};
+use crate::random::Rng;
+
+/// How a particle is drawn over its lifetime.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Appearance {
+    /// A square of `size` pixels whose colour blends from `start` to `end`, alpha included.
+    Color { start: u32, end: u32, size: i32 },
+    /// Steps through the debris frames of a box skin, from whole to crumbled.
+    Debris(BoxSkin),
+}
+
+/// Describes the particles an emitter spawns. Ranges are `(min, max)` and each particle
+/// picks a random value within them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EmitterConfig {
+    /// Particles spawned immediately when the emitter starts.
+    pub burst: u32,
+    /// Particles spawned per frame after the burst, while the emitter is alive.
+    pub spawn_rate: f32,
+    /// Frames the emitter keeps spawning for. Zero for a single burst.
+    pub duration: u32,
+    /// Frames each particle lives.
+    pub lifetime: (u32, u32),
+    /// Direction of the initial velocity in radians, where 0 points right and -PI/2 up.
+    pub angle: (f32, f32),
+    /// Initial speed in pixels per frame.
+    pub speed: (f32, f32),
+    /// Random offset of the spawn position from the emitter, on both axes.
+    pub spread: f32,
+    /// Downward acceleration in pixels per frame squared.
+    pub gravity: f32,
+    pub appearance: Appearance,
+    pub blend_mode: BlendMode,
+}
+
+impl EmitterConfig {
+    /// Chunks flying off a box which breaks.
+    pub fn debris(skin: BoxSkin) -> Self {
+        Self {
+            burst: 10,
+            spawn_rate: 0.0,
+            duration: 0,
+            lifetime: (25, 45),
+            angle: (-PI, 0.0),
+            speed: (0.8, 2.5),
+            spread: 6.0,
+            gravity: 0.2,
+            appearance: Appearance::Debris(skin),
+            blend_mode: BlendMode::Alpha,
+        }
+    }
+
+    /// Puffs of dust kicked up by the player landing.
+    pub fn landing_dust() -> Self {
+        Self {
+            burst: 6,
+            spawn_rate: 0.0,
+            duration: 0,
+            lifetime: (12, 20),
+            angle: (-PI, 0.0),
+            speed: (0.2, 0.8),
+            spread: 4.0,
+            gravity: -0.01,
+            appearance: Appearance::Color { start: 0xC0D8C8A0, end: 0x00D8C8A0, size: 2 },
+            blend_mode: BlendMode::Alpha,
+        }
+    }
+
+    /// A wider, faster cloud of dust for landing after a long fall.
+    pub fn heavy_landing_dust() -> Self {
+        Self {
+            burst: 14,
+            speed: (0.5, 1.5),
+            spread: 6.0,
+            lifetime: (16, 28),
+            ..Self::landing_dust()
+        }
+    }
+
+    /// Hot sparks thrown out by an explosion.
+    pub fn explosion_sparks() -> Self {
+        Self {
+            burst: 24,
+            spawn_rate: 0.0,
+            duration: 0,
+            lifetime: (15, 30),
+            angle: (-PI, PI),
+            speed: (1.5, 4.0),
+            spread: 4.0,
+            gravity: 0.1,
+            appearance: Appearance::Color { start: 0xFFFFE080, end: 0x00FF4010, size: 2 },
+            blend_mode: BlendMode::Additive,
+        }
+    }
+
+    /// Smoke which keeps rising from an explosion for a moment.
+    pub fn explosion_smoke() -> Self {
+        Self {
+            burst: 4,
+            spawn_rate: 0.5,
+            duration: 20,
+            lifetime: (30, 50),
+            angle: (-PI * 0.75, -PI * 0.25),
+            speed: (0.2, 0.6),
+            spread: 8.0,
+            gravity: -0.01,
+            appearance: Appearance::Color { start: 0xA0404040, end: 0x00808080, size: 3 },
+            blend_mode: BlendMode::Alpha,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Particle {
+    x: f32,
+    y: f32,
+    vx: f32,
+    vy: f32,
+    age: u32,
+    lifetime: u32,
+    gravity: f32,
+    appearance: Appearance,
+    blend_mode: BlendMode,
+}
+
+impl Particle {
+    /// Progress through the particle's life from 0.0 to 1.0.
+    fn progress(&self) -> f32 {
+        self.age as f32 / self.lifetime.max(1) as f32
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Emitter {
+    config: EmitterConfig,
+    x: f32,
+    y: f32,
+    frames_left: u32,
+    // Fractional particles carried over between frames, so low spawn rates still spawn
+    pending: f32,
+}
+
+/// Spawns, moves and draws short-lived particles for effects such as debris and dust.
+pub struct ParticleSystem {
+    particles: Vec<Particle>,
+    emitters: Vec<Emitter>,
+    rng: Rng,
+}
+
+impl ParticleSystem {
+    /// Creates an empty system which varies its particles with `rng`.
+    pub fn new(rng: Rng) -> Self {
+        Self {
+            particles: Vec::new(),
+            emitters: Vec::new(),
+            rng,
+        }
+    }
+
+    /// Starts an emitter at the given position. Its burst is spawned right away.
+    pub fn emit(&mut self, config: EmitterConfig, x: f32, y: f32) {
+        for _ in 0..config.burst {
+            self.spawn(&config, x, y);
+        }
+
+        if config.duration > 0 && config.spawn_rate > 0.0 {
+            self.emitters.push(Emitter { config, x, y, frames_left: config.duration, pending: 0.0 });
+        }
+    }
+
+    fn spawn(&mut self, config: &EmitterConfig, x: f32, y: f32) {
+        let angle = self.rng.range(config.angle.0, config.angle.1);
+        let speed = self.rng.range(config.speed.0, config.speed.1);
+        self.particles.push(Particle {
+            x: x + self.rng.range(-config.spread, config.spread),
+            y: y + self.rng.range(-config.spread, config.spread),
+            vx: angle.cos() * speed,
+            vy: angle.sin() * speed,
+            age: 0,
+            lifetime: self.rng.range_u32(config.lifetime.0, config.lifetime.1),
+            gravity: config.gravity,
+            appearance: config.appearance,
+            blend_mode: config.blend_mode,
+        });
+    }
+
+    /// Advances all emitters and particles by one frame.
+    pub fn update(&mut self) {
+        let mut emitters = std::mem::take(&mut self.emitters);
+        for emitter in emitters.iter_mut() {
+            emitter.pending += emitter.config.spawn_rate;
+            while emitter.pending >= 1.0 {
+                emitter.pending -= 1.0;
+                self.spawn(&emitter.config, emitter.x, emitter.y);
+            }
+            emitter.frames_left = emitter.frames_left.saturating_sub(1);
+        }
+        emitters.retain(|emitter| emitter.frames_left > 0);
+        self.emitters = emitters;
+
+        self.particles.retain_mut(|particle| {
+            particle.vy += particle.gravity;
+            particle.x += particle.vx;
+            particle.y += particle.vy;
+            particle.age += 1;
+            particle.age < particle.lifetime
+        });
+    }
+
+    /// Queues all live particles on the world layer, above the boxes.
+    pub fn queue<'s>(&self, sprites: &'s Sprites, render_queue: &mut RenderQueue<'s>) {
+        for particle in &self.particles {
+            let progress = particle.progress();
+            let position = (particle.x as i32, particle.y as i32);
+
+            match particle.appearance {
+                Appearance::Color { start, end, size } => {
+                    let square = Shape::Rect { position, size: (size, size), filled: true };
+                    render_queue.push_shape(Layer::World, 2, square, lerp_color(start, end, progress), particle.blend_mode);
+                }
+                Appearance::Debris(skin) => {
+                    let frames = sprites.debris(skin);
+                    let frame = ((progress * frames.len() as f32) as usize).min(frames.len() - 1);
+                    render_queue.push_sprite(Layer::World, 2, position.0, position.1, &frames[frame], particle.blend_mode);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn puff(burst: u32, spawn_rate: f32, duration: u32, lifetime: u32) -> EmitterConfig {
+        EmitterConfig {
+            burst,
+            spawn_rate,
+            duration,
+            lifetime: (lifetime, lifetime),
+            angle: (0.0, 0.0),
+            speed: (1.0, 1.0),
+            spread: 0.0,
+            gravity: 0.5,
+            appearance: Appearance::Color { start: 0xFFFFFFFF, end: 0x00FFFFFF, size: 1 },
+            blend_mode: BlendMode::Alpha,
+        }
+    }
+
+    #[test]
+    fn bursts_spawn_at_once_and_die_after_their_lifetime() {
+        let mut particles = ParticleSystem::new(Rng::new(1));
+        particles.emit(puff(5, 0.0, 0, 3), 10.0, 20.0);
+        assert_eq!(particles.particles.len(), 5);
+        assert!(particles.emitters.is_empty());
+
+        particles.update();
+        particles.update();
+        assert_eq!(particles.particles.len(), 5);
+        particles.update();
+        assert!(particles.particles.is_empty());
+    }
+
+    #[test]
+    fn particles_move_and_fall() {
+        let mut particles = ParticleSystem::new(Rng::new(1));
+        particles.emit(puff(1, 0.0, 0, 10), 10.0, 20.0);
+        particles.update();
+        particles.update();
+
+        let particle = particles.particles[0];
+        assert_eq!((particle.x, particle.y), (12.0, 21.5));
+        assert_eq!(particle.progress(), 0.2);
+    }
+
+    #[test]
+    fn emitters_carry_fractional_spawns_over() {
+        let mut particles = ParticleSystem::new(Rng::new(1));
+        particles.emit(puff(0, 0.5, 4, 100), 0.0, 0.0);
+        for _ in 0..4 {
+            particles.update();
+        }
+        assert_eq!(particles.particles.len(), 2);
+        assert!(particles.emitters.is_empty());
+    }
+
+    #[test]
+    fn same_seed_spawns_the_same_particles() {
+        let positions = |seed| {
+            let mut particles = ParticleSystem::new(Rng::new(seed));
+            particles.emit(EmitterConfig::explosion_sparks(), 50.0, 50.0);
+            particles.update();
+            particles.particles.iter().map(|particle| (particle.x, particle.y)).collect::<Vec<_>>()
+        };
+        assert_eq!(positions(3), positions(3));
+        assert_ne!(positions(3), positions(4));
+    }
+}
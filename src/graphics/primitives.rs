@@ -0,0 +1,285 @@
+use crate::graphics::blend::{blend_pixel, BlendMode};
+
+/// A point in buffer coordinates. Coordinates may lie outside the buffer, everything drawn
+/// is clipped against the buffer edges.
+pub type Point = (i32, i32);
+
+/// A shape which can be queued in the render queue and drawn later.
+#[derive(Debug, Clone)]
+pub enum Shape {
+    Line { from: Point, to: Point },
+    Rect { position: Point, size: (i32, i32), filled: bool },
+    Circle { center: Point, radius: i32, filled: bool },
+    Polygon { points: Vec<Point>, filled: bool },
+}
+
+/// Draws a shape into the buffer with the given colour and blend mode.
+pub fn draw_shape(shape: &Shape, color: u32, blend_mode: BlendMode, buffer: &mut [u32], buffer_width: usize) {
+    match shape {
+        Shape::Line { from, to } => draw_line(*from, *to, color, blend_mode, buffer, buffer_width),
+        Shape::Rect { position, size, filled: false } => draw_rect(*position, *size, color, blend_mode, buffer, buffer_width),
+        Shape::Rect { position, size, filled: true } => fill_rect(*position, *size, color, blend_mode, buffer, buffer_width),
+        Shape::Circle { center, radius, filled: false } => draw_circle(*center, *radius, color, blend_mode, buffer, buffer_width),
+        Shape::Circle { center, radius, filled: true } => fill_circle(*center, *radius, color, blend_mode, buffer, buffer_width),
+        Shape::Polygon { points, filled: false } => draw_polygon(points, color, blend_mode, buffer, buffer_width),
+        Shape::Polygon { points, filled: true } => fill_polygon(points, color, blend_mode, buffer, buffer_width),
+    }
+}
+
+/// Blends a single ARGB pixel into the buffer, ignoring coordinates outside the buffer.
+///
+/// This is the clipping equivalent of `set_pixel` which all primitives are built on, so the
+/// alpha channel of `color` is respected just like the pixels of a sprite.
+pub fn blend_point(x: i32, y: i32, color: u32, blend_mode: BlendMode, buffer: &mut [u32], buffer_width: usize) {
+    if x < 0 || y < 0 || x as usize >= buffer_width {
+        return;
+    }
+
+    let index = y as usize * buffer_width + x as usize;
+    if index < buffer.len() {
+        buffer[index] = blend_pixel(buffer[index], color, blend_mode);
+    }
+}
+
+/// Blends a horizontal run of pixels from `x_start` to `x_end` (inclusive), clipped to the buffer.
+fn blend_span(x_start: i32, x_end: i32, y: i32, color: u32, blend_mode: BlendMode, buffer: &mut [u32], buffer_width: usize) {
+    let buffer_height = (buffer.len() / buffer_width.max(1)) as i32;
+    if y < 0 || y >= buffer_height {
+        return;
+    }
+
+    let x_start = x_start.max(0);
+    let x_end = x_end.min(buffer_width as i32 - 1);
+    let row_start = y as usize * buffer_width;
+    for x in x_start..=x_end {
+        let index = row_start + x as usize;
+        buffer[index] = blend_pixel(buffer[index], color, blend_mode);
+    }
+}
+
+/// Draws a line between two points (inclusive) using Bresenham's algorithm.
+pub fn draw_line(from: Point, to: Point, color: u32, blend_mode: BlendMode, buffer: &mut [u32], buffer_width: usize) {
+    let (mut x, mut y) = from;
+    let dx = (to.0 - x).abs();
+    let dy = -(to.1 - y).abs();
+    let step_x = if x < to.0 { 1 } else { -1 };
+    let step_y = if y < to.1 { 1 } else { -1 };
+    let mut error = dx + dy;
+
+    loop {
+        blend_point(x, y, color, blend_mode, buffer, buffer_width);
+        if x == to.0 && y == to.1 {
+            break;
+        }
+
+        let doubled_error = 2 * error;
+        if doubled_error >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled_error <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+/// Draws the one pixel wide outline of a rectangle with its top-left corner at `position`.
+pub fn draw_rect(position: Point, size: (i32, i32), color: u32, blend_mode: BlendMode, buffer: &mut [u32], buffer_width: usize) {
+    let (x, y) = position;
+    let (width, height) = size;
+    if width <= 0 || height <= 0 {
+        return;
+    }
+
+    let (right, bottom) = (x + width - 1, y + height - 1);
+    blend_span(x, right, y, color, blend_mode, buffer, buffer_width);
+    if bottom != y {
+        blend_span(x, right, bottom, color, blend_mode, buffer, buffer_width);
+    }
+
+    // Vertical edges skip the corners, which were drawn by the horizontal edges
+    for row in y + 1..bottom {
+        blend_point(x, row, color, blend_mode, buffer, buffer_width);
+        if right != x {
+            blend_point(right, row, color, blend_mode, buffer, buffer_width);
+        }
+    }
+}
+
+/// Fills a rectangle with its top-left corner at `position`.
+pub fn fill_rect(position: Point, size: (i32, i32), color: u32, blend_mode: BlendMode, buffer: &mut [u32], buffer_width: usize) {
+    let (x, y) = position;
+    let (width, height) = size;
+    for row in y..y + height {
+        blend_span(x, x + width - 1, row, color, blend_mode, buffer, buffer_width);
+    }
+}
+
+/// Draws the outline of a circle using the midpoint circle algorithm.
+pub fn draw_circle(center: Point, radius: i32, color: u32, blend_mode: BlendMode, buffer: &mut [u32], buffer_width: usize) {
+    if radius < 0 {
+        return;
+    }
+
+    let (cx, cy) = center;
+    let (mut x, mut y) = (radius, 0);
+    let mut error = 1 - radius;
+
+    while x >= y {
+        // Octants are mirrored, so points on the diagonals and axes are only drawn once
+        let mut points = vec![(x, y), (-x, y), (x, -y), (-x, -y), (y, x), (-y, x), (y, -x), (-y, -x)];
+        points.sort_unstable();
+        points.dedup();
+        for (px, py) in points {
+            blend_point(cx + px, cy + py, color, blend_mode, buffer, buffer_width);
+        }
+
+        y += 1;
+        if error < 0 {
+            error += 2 * y + 1;
+        } else {
+            x -= 1;
+            error += 2 * (y - x) + 1;
+        }
+    }
+}
+
+/// Fills a circle, drawing each row as a single span so no pixel is blended twice.
+pub fn fill_circle(center: Point, radius: i32, color: u32, blend_mode: BlendMode, buffer: &mut [u32], buffer_width: usize) {
+    if radius < 0 {
+        return;
+    }
+
+    let (cx, cy) = center;
+    for dy in -radius..=radius {
+        let half_width = ((radius * radius - dy * dy) as f32).sqrt() as i32;
+        blend_span(cx - half_width, cx + half_width, cy + dy, color, blend_mode, buffer, buffer_width);
+    }
+}
+
+/// Draws the closed outline of a polygon.
+pub fn draw_polygon(points: &[Point], color: u32, blend_mode: BlendMode, buffer: &mut [u32], buffer_width: usize) {
+    for (index, from) in points.iter().enumerate() {
+        let to = points[(index + 1) % points.len()];
+        draw_line(*from, to, color, blend_mode, buffer, buffer_width);
+    }
+}
+
+/// Fills a simple polygon using an even-odd scanline fill.
+///
+/// Each scanline is sampled at the pixel centre, which keeps adjacent polygons sharing an
+/// edge from overlapping.
+pub fn fill_polygon(points: &[Point], color: u32, blend_mode: BlendMode, buffer: &mut [u32], buffer_width: usize) {
+    if points.len() < 3 {
+        return;
+    }
+
+    let buffer_height = (buffer.len() / buffer_width.max(1)) as i32;
+    let min_y = points.iter().map(|point| point.1).min().unwrap_or(0).max(0);
+    let max_y = points.iter().map(|point| point.1).max().unwrap_or(0).min(buffer_height - 1);
+
+    let mut crossings: Vec<f32> = Vec::with_capacity(points.len());
+    for y in min_y..=max_y {
+        let scan_y = y as f32 + 0.5;
+        crossings.clear();
+
+        for (index, &(x0, y0)) in points.iter().enumerate() {
+            let (x1, y1) = points[(index + 1) % points.len()];
+            let (top, bottom) = (y0.min(y1) as f32, y0.max(y1) as f32);
+            if scan_y >= top && scan_y < bottom {
+                let t = (scan_y - y0 as f32) / (y1 - y0) as f32;
+                crossings.push(x0 as f32 + t * (x1 - x0) as f32);
+            }
+        }
+
+        crossings.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        for pair in crossings.chunks_exact(2) {
+            let x_start = (pair[0] - 0.5).ceil() as i32;
+            let x_end = (pair[1] - 0.5).ceil() as i32 - 1;
+            blend_span(x_start, x_end, y, color, blend_mode, buffer, buffer_width);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: usize = 8;
+    // Adding one step of blue per draw shows pixels which were blended more than once
+    const STEP: u32 = 0xFF000001;
+
+    fn buffer() -> Vec<u32> {
+        vec![0xFF000000; SIZE * SIZE]
+    }
+
+    fn draw(shape: Shape) -> Vec<u32> {
+        let mut buffer = buffer();
+        draw_shape(&shape, STEP, BlendMode::Additive, &mut buffer, SIZE);
+        buffer
+    }
+
+    /// The buffer as rows of `#` for drawn pixels and `.` for untouched ones.
+    fn rows(buffer: &[u32]) -> Vec<String> {
+        buffer.chunks(SIZE).map(|row| row.iter().map(|&pixel| if pixel & 0xFF > 0 { '#' } else { '.' }).collect()).collect()
+    }
+
+    fn blended_at_most_once(buffer: &[u32]) -> bool {
+        buffer.iter().all(|&pixel| pixel & 0xFF <= 1)
+    }
+
+    #[test]
+    fn lines_include_both_ends() {
+        let buffer = draw(Shape::Line { from: (1, 1), to: (4, 2) });
+        assert_eq!(&rows(&buffer)[..3], ["........", ".##.....", "...##..."]);
+    }
+
+    #[test]
+    fn shapes_are_clipped_to_the_buffer() {
+        let buffer = draw(Shape::Line { from: (-4, 3), to: (12, 3) });
+        assert_eq!(rows(&buffer)[3], "########");
+        assert_eq!(buffer.iter().filter(|&&pixel| pixel & 0xFF > 0).count(), SIZE);
+
+        let buffer = draw(Shape::Rect { position: (-2, -2), size: (5, 5), filled: true });
+        assert_eq!(&rows(&buffer)[..4], ["###.....", "###.....", "###.....", "........"]);
+
+        // Entirely outside draws nothing, and doesn't wrap around to the next row
+        let buffer = draw(Shape::Circle { center: (20, 3), radius: 4, filled: true });
+        assert!(buffer.iter().all(|&pixel| pixel == 0xFF000000));
+    }
+
+    #[test]
+    fn rect_outline_draws_each_pixel_once() {
+        let buffer = draw(Shape::Rect { position: (1, 1), size: (4, 3), filled: false });
+        assert_eq!(&rows(&buffer)[..5], ["........", ".####...", ".#..#...", ".####...", "........"]);
+        assert!(blended_at_most_once(&buffer));
+    }
+
+    #[test]
+    fn circles_draw_each_pixel_once() {
+        let outline = draw(Shape::Circle { center: (3, 3), radius: 3, filled: false });
+        assert!(blended_at_most_once(&outline));
+        assert_eq!(rows(&outline)[3], "#.....#.");
+
+        let filled = draw(Shape::Circle { center: (3, 3), radius: 3, filled: true });
+        assert!(blended_at_most_once(&filled));
+        assert_eq!(rows(&filled)[3], "#######.");
+    }
+
+    #[test]
+    fn polygons_sharing_an_edge_do_not_overlap() {
+        let mut buffer = buffer();
+        for points in [vec![(0, 0), (8, 0), (0, 8)], vec![(8, 0), (8, 8), (0, 8)]] {
+            draw_shape(&Shape::Polygon { points, filled: true }, STEP, BlendMode::Additive, &mut buffer, SIZE);
+        }
+        assert!(blended_at_most_once(&buffer));
+        assert!(buffer.iter().all(|&pixel| pixel & 0xFF == 1));
+    }
+
+    #[test]
+    fn polygon_outline_is_closed() {
+        let buffer = draw(Shape::Polygon { points: vec![(1, 1), (5, 1), (5, 5)], filled: false });
+        assert_eq!(&rows(&buffer)[..6], ["........", ".#####..", "..#..#..", "...#.#..", "....##..", ".....#.."]);
+    }
+}
@@ -0,0 +1,101 @@
+use crate::graphics::blend::BlendMode;
+use crate::graphics::font::{BitmapFont, TextStyle};
+use crate::graphics::primitives::{draw_shape, Shape};
+use crate::graphics::sprites::{draw_sprite_blended, Sprite};
+use crate::graphics::surface::Surface;
+
+/// Render layers, drawn from back to front in declaration order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layer {
+    Background,
+    World,
+    Entities,
+    Effects,
+    Hud,
+    Debug,
+}
+
+/// Something that can be drawn by the render queue.
+pub enum Drawable<'s> {
+    Sprite(&'s Sprite),
+    /// An offscreen surface, composited with the given opacity.
+    Surface(&'s Surface, f32),
+    /// A primitive shape in absolute buffer coordinates, drawn with the given ARGB colour.
+    Shape(Shape, u32),
+    /// A string of text drawn with the given font and style. Text is always alpha blended.
+    Text(String, &'s BitmapFont, TextStyle),
+}
+
+/// A single queued draw, carrying everything needed to order and blend it.
+pub struct DrawCommand<'s> {
+    pub layer: Layer,
+    pub z: i32,
+    pub blend_mode: BlendMode,
+    pub x: i32,
+    pub y: i32,
+    pub drawable: Drawable<'s>,
+}
+
+/// Collects draw commands for a frame and flushes them sorted by layer and z value.
+///
+/// Commands sharing the same layer and z value are drawn in submission order, so the
+/// queue only reorders draws that explicitly ask for it.
+#[derive(Default)]
+pub struct RenderQueue<'s> {
+    commands: Vec<DrawCommand<'s>>,
+}
+
+impl<'s> RenderQueue<'s> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, command: DrawCommand<'s>) {
+        self.commands.push(command);
+    }
+
+    /// Queues a sprite at the given position.
+    pub fn push_sprite(&mut self, layer: Layer, z: i32, x: i32, y: i32, sprite: &'s Sprite, blend_mode: BlendMode) {
+        self.push(DrawCommand { layer, z, blend_mode, x, y, drawable: Drawable::Sprite(sprite) });
+    }
+
+    /// Queues an offscreen surface at the given position.
+    #[allow(clippy::too_many_arguments)]
+    pub fn push_surface(&mut self, layer: Layer, z: i32, x: i32, y: i32, surface: &'s Surface, opacity: f32, blend_mode: BlendMode) {
+        self.push(DrawCommand { layer, z, blend_mode, x, y, drawable: Drawable::Surface(surface, opacity) });
+    }
+
+    /// Queues a primitive shape. Shapes carry their own coordinates, so no position is given.
+    pub fn push_shape(&mut self, layer: Layer, z: i32, shape: Shape, color: u32, blend_mode: BlendMode) {
+        self.push(DrawCommand { layer, z, blend_mode, x: 0, y: 0, drawable: Drawable::Shape(shape, color) });
+    }
+
+    /// Queues text at the given position, see `BitmapFont::draw_text` for how it is aligned.
+    #[allow(clippy::too_many_arguments)]
+    pub fn push_text(&mut self, layer: Layer, z: i32, x: i32, y: i32, text: String, font: &'s BitmapFont, style: TextStyle) {
+        self.push(DrawCommand { layer, z, blend_mode: BlendMode::Alpha, x, y, drawable: Drawable::Text(text, font, style) });
+    }
+
+    /// Sorts the queued commands and draws them into the buffer, leaving the queue empty.
+    pub fn flush(&mut self, buffer: &mut [u32], buffer_width: usize) {
+        // sort_by_key is stable, which preserves submission order within a layer/z pair
+        self.commands.sort_by_key(|command| (command.layer, command.z));
+
+        for command in self.commands.drain(..) {
+            match command.drawable {
+                Drawable::Sprite(sprite) => {
+                    draw_sprite_blended(command.x, command.y, sprite, command.blend_mode, buffer, buffer_width);
+                }
+                Drawable::Surface(surface, opacity) => {
+                    surface.composite(command.x, command.y, opacity, command.blend_mode, buffer, buffer_width);
+                }
+                Drawable::Shape(shape, color) => {
+                    draw_shape(&shape, color, command.blend_mode, buffer, buffer_width);
+                }
+                Drawable::Text(text, font, style) => {
+                    font.draw_text(&text, command.x, command.y, &style, buffer, buffer_width);
+                }
+            }
+        }
+    }
+}
@@ -1,12 +1,14 @@
 
+use minifb::Window;
+
 use crate::state::GameState;
 
-pub fn render_pixel_buffer(game_state: &mut GameState) {
+pub fn render_pixel_buffer(game_state: &mut GameState, window: &mut Window) {
     // Scale the buffer to the screen resolution
     scale_buffer(&game_state.window_buffer, &mut game_state.scaled_buffer, game_state.all_maps[game_state.current_map_index].width, game_state.all_maps[game_state.current_map_index].height, game_state.window_width, game_state.window_height);
 
     // Draw the scaled buffer onto the window
-    game_state.window.update_with_buffer(&game_state.scaled_buffer, game_state.window_width, game_state.window_height).unwrap();
+    window.update_with_buffer(game_state.scaled_buffer, game_state.window_width, game_state.window_height).unwrap();
 }
 
 // Function to scale a buffer to a different resolution
@@ -1,5 +1,37 @@
+use std::collections::HashMap;
+
 use image::GenericImageView;
 
+use crate::diagnostics::{log_debug, log_info, log_trace};
+use crate::graphics::blend::{blend_pixel, tint, BlendMode};
+
+const DEBRIS_SIZE: u32 = 4;
+
+/// How a box is drawn. The state side picks one for each box material.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BoxSkin {
+    Wood,
+    Metal,
+    Stone,
+    Ice,
+    Explosive,
+}
+
+impl BoxSkin {
+    pub const ALL: [BoxSkin; 5] = [BoxSkin::Wood, BoxSkin::Metal, BoxSkin::Stone, BoxSkin::Ice, BoxSkin::Explosive];
+
+    /// Colour the metal box frames are multiplied with to draw this skin.
+    pub fn tint(self) -> u32 {
+        match self {
+            BoxSkin::Wood => 0xFFD09050,
+            BoxSkin::Metal => 0xFFFFFFFF,
+            BoxSkin::Stone => 0xFF909090,
+            BoxSkin::Ice => 0xFFA0E0FF,
+            BoxSkin::Explosive => 0xFFFF6050,
+        }
+    }
+}
+
 pub struct Sprite {
     pub width: u32,  // Width of the sprite in pixels
     pub height: u32, // Height of the sprite in pixels
@@ -10,6 +42,20 @@ impl Sprite {
     fn new(width: u32, height: u32, data: Vec<u32>) -> Self {
         Self { width, height, data }
     }
+
+    /// Returns the rectangle of the sprite starting at (x, y) as a new sprite.
+    pub fn cropped(&self, x: u32, y: u32, width: u32, height: u32) -> Self {
+        let data = (y..y + height)
+            .flat_map(|row| (x..x + width).map(move |col| (row, col)))
+            .map(|(row, col)| self.data[(row * self.width + col) as usize])
+            .collect();
+        Self::new(width, height, data)
+    }
+
+    /// Returns a copy of the sprite with every pixel multiplied by the tint colour.
+    pub fn tinted(&self, color: u32) -> Self {
+        Self::new(self.width, self.height, self.data.iter().map(|&pixel| tint(pixel, color)).collect())
+    }
 }
 
 pub struct Sprites {
@@ -21,12 +67,21 @@ pub struct Sprites {
     pub grass: Vec<Sprite>,
     pub sky: Vec<Sprite>,
     pub blue_background: Vec<Sprite>,
-    pub metal_box: Vec<Sprite>
+    pub metal_box: Vec<Sprite>,
+    pub wood_box: Vec<Sprite>,
+    pub stone_box: Vec<Sprite>,
+    pub ice_box: Vec<Sprite>,
+    pub explosive_box: Vec<Sprite>,
+    pub box_debris: HashMap<BoxSkin, Vec<Sprite>>
 }
 
 impl Sprites {
     pub fn new() -> Self {
-        Self {
+        // The other box skins are tinted variants of the metal box frames
+        let metal_box = load_sprites_from_map("assets/box.png", 16, 16);
+        let tinted_boxes = |skin: BoxSkin| metal_box.iter().map(|sprite| sprite.tinted(skin.tint())).collect();
+
+        let mut sprites = Self {
             player: load_sprites_from_map("assets/player.png", 23, 33),
             shadow: load_sprites_from_map("assets/shadow.png", 24, 10),
             game_over: load_sprites_from_map("assets/game_over.png", 256, 224),
@@ -35,8 +90,44 @@ impl Sprites {
             grass: load_sprites_from_map("assets/grass.png", 256, 17),
             sky: load_sprites_from_map("assets/sky.png", 256, 134),
             blue_background: load_sprites_from_map("assets/blue_background.png", 256, 224),
-            metal_box: load_sprites_from_map("assets/box.png", 16, 16),
+            wood_box: tinted_boxes(BoxSkin::Wood),
+            stone_box: tinted_boxes(BoxSkin::Stone),
+            ice_box: tinted_boxes(BoxSkin::Ice),
+            explosive_box: tinted_boxes(BoxSkin::Explosive),
+            metal_box,
+            box_debris: HashMap::new(),
+        };
+
+        // Debris chunks are cut from the middle of each box frame, so they crumble along with it
+        for skin in BoxSkin::ALL {
+            let debris = sprites.boxes(skin).iter().map(|frame| frame.cropped(6, 6, DEBRIS_SIZE, DEBRIS_SIZE)).collect();
+            sprites.box_debris.insert(skin, debris);
         }
+
+        sprites
+    }
+
+    /// Returns the debris frames for a skin, from whole to most crumbled.
+    pub fn debris(&self, skin: BoxSkin) -> &[Sprite] {
+        &self.box_debris[&skin]
+    }
+
+    /// Returns the box frames for a skin, from undamaged to most damaged.
+    pub fn boxes(&self, skin: BoxSkin) -> &[Sprite] {
+        match skin {
+            BoxSkin::Wood => &self.wood_box,
+            BoxSkin::Metal => &self.metal_box,
+            BoxSkin::Stone => &self.stone_box,
+            BoxSkin::Ice => &self.ice_box,
+            BoxSkin::Explosive => &self.explosive_box,
+        }
+    }
+
+    /// Returns the frame to draw for a box which took `damage` kicks, the last frame once it
+    /// took more kicks than there are frames.
+    pub fn box_frame(&self, skin: BoxSkin, damage: u8) -> &Sprite {
+        let frames = self.boxes(skin);
+        &frames[(damage as usize).min(frames.len() - 1)]
     }
 }
 
@@ -57,30 +148,30 @@ pub fn load_sprites_from_map(sprite_map_path: &str, sprite_width: u32, sprite_he
     let sprite_map = image::open(sprite_map_path).expect(&format!("Failed to open sprite map at {}", sprite_map_path));
     let (map_width, map_height) = sprite_map.dimensions();
 
-    println!("Sprite map loaded from {}", sprite_map_path);
-    println!("Sprite map dimensions: {}x{}", map_width, map_height);
+    log_debug!(Assets, "Sprite map loaded from {}", sprite_map_path);
+    log_debug!(Assets, "Sprite map dimensions: {}x{}", map_width, map_height);
 
     // Calculate the number of sprites in each dimension
     let sprites_x = map_width / sprite_width;
     let sprites_y = map_height / sprite_height;
 
-    println!("Sprites x: {}", sprites_x);
-    println!("Sprites y: {}", sprites_y);
+    log_trace!(Assets, "Sprites x: {}", sprites_x);
+    log_trace!(Assets, "Sprites y: {}", sprites_y);
 
     // Extract individual sprites and store them in a buffer
     let mut sprites = Vec::new();
     for y in 0..sprites_y {
         for x in 0..sprites_x {
-            println!("Extracting sprite at ({}, {})", x, y);
+            log_trace!(Assets, "Extracting sprite at ({}, {})", x, y);
             let sprite = sprite_map.crop_imm(x * sprite_width, y * sprite_height, sprite_width, sprite_height);
             let buffer = img_to_buffer(&sprite);
-            println!("Sprite extracted: {}x{}, buffer length: {}", sprite_width, sprite_height, buffer.len());
+            log_trace!(Assets, "Sprite extracted: {}x{}, buffer length: {}", sprite_width, sprite_height, buffer.len());
             let new_sprite = Sprite::new(sprite_width, sprite_height, buffer);
             sprites.push(new_sprite);
         }
     }
 
-    println!("Total sprites extracted: {}\n", sprites.len());
+    log_info!(Assets, "Extracted {} sprites from {}", sprites.len(), sprite_map_path);
 
     // Return the vector of sprites
     sprites
@@ -105,75 +196,38 @@ pub fn img_to_buffer(img: &image::DynamicImage) -> Vec<u32> {
     }).collect()
 }
 
-/// Draws a sprite onto the window buffer at the specified coordinates, with alpha blending.
+/// Draws a sprite onto the window buffer at the specified coordinates using the given blend mode.
+///
+/// The coordinates may be negative, and the sprite is clipped against all four edges of the
+/// buffer instead of wrapping around to the next row.
 ///
 /// # Parameters
 /// - `x`: The x-coordinate where the sprite will be drawn.
 /// - `y`: The y-coordinate where the sprite will be drawn.
-/// - `sprite`: A tuple containing the sprite's width, height, and pixel data. The pixel data is a vector of `u32` values representing RGBA colors.
-/// - `window_buffer`: A mutable slice of `u32` representing the pixels of the window buffer. Each `u32` value represents an RGBA color.
+/// - `sprite`: The sprite to draw.
+/// - `blend_mode`: How the sprite's pixels are combined with the pixels already in the buffer.
+/// - `window_buffer`: A mutable slice of `u32` representing the pixels of the window buffer.
 /// - `window_width`: The width of the window in pixels.
-///
-/// This function uses alpha blending to combine the sprite's pixels with the corresponding pixels in the window buffer. Only non-transparent pixels in the sprite are drawn.
-///
-/// # Alpha Blending
-/// Alpha blending is a process used in computer graphics to combine a foreground image with a background image, resulting in a composite image.
-/// The alpha value determines the transparency level of the pixel:
-/// - An alpha value of 0 means the pixel is completely transparent.
-/// - An alpha value of 255 (0xFF) means the pixel is completely opaque.
-///
-/// The formula for alpha blending is:
-/// ```
-/// blended_color = (foreground_color * alpha + background_color * (255 - alpha)) / 255
-/// ```
-///
-/// # ARGB Color Palette
-/// Each `u32` value in the pixel data represents a color in ARGB format:
-/// - The highest 8 bits represent the alpha (transparency) channel.
-/// - The next 8 bits represent the red channel.
-/// - The next 8 bits represent the green channel.
-/// - The lowest 8 bits represent the blue channel.
-///
-/// For example, a color represented as `0x80FF00FF` means:
-/// - Alpha: 0x80 (128 in decimal, semi-transparent)
-/// - Red: 0xFF (255 in decimal, full intensity)
-/// - Green: 0x00 (0 in decimal, no intensity)
-/// - Blue: 0xFF (255 in decimal, full intensity)
-///
-/// # Example Usage
-/// ```rust
-/// let sprite = (16, 16, vec![0x80FF00FF; 256]); // A semi-transparent magenta 16x16 sprite
-/// let mut window_buffer = vec![0xFFFFFFFF; 800 * 600]; // A white 800x600 window buffer
-/// draw_sprite(10, 10, &sprite, &mut window_buffer, 800);
-/// ```
-pub fn draw_sprite(x: usize, y: usize, sprite: &Sprite, window_buffer: &mut [u32], window_width: usize) {
-
-    for row in 0..sprite.height as usize {
-        for col in 0..sprite.width as usize {
-            let sprite_pixel_index = row * (sprite.width as usize) + col;
-            let window_pixel_index = (y + row) * window_width + (x + col);
-
-            if window_pixel_index < window_buffer.len() {
-                let sprite_pixel = sprite.data[sprite_pixel_index];
-                let sprite_alpha = (sprite_pixel >> 24) & 0xFF; // Extract alpha channel from sprite pixel
-                let sprite_rgb = sprite_pixel & 0x00FFFFFF; // Extract RGB channels from sprite pixel
-
-                if sprite_alpha > 0 { // Only blend if the pixel is not fully transparent
-                    let window_pixel = window_buffer[window_pixel_index];
-                    let window_rgb = window_pixel & 0x00FFFFFF; // Extract RGB channels from window buffer pixel
-
-                    // Calculate blended color using alpha blending formula
-                    let blended_r = ((sprite_rgb >> 16) & 0xFF) * sprite_alpha / 255 + ((window_rgb >> 16) & 0xFF) * (255 - sprite_alpha) / 255;
-                    let blended_g = ((sprite_rgb >> 8) & 0xFF) * sprite_alpha / 255 + ((window_rgb >> 8) & 0xFF) * (255 - sprite_alpha) / 255;
-                    let blended_b = (sprite_rgb & 0xFF) * sprite_alpha / 255 + (window_rgb & 0xFF) * (255 - sprite_alpha) / 255;
-
-                    // Combine blended color with full alpha
-                    let blended_pixel = 0xFF000000 | (blended_r & 0xFF) << 16 | (blended_g & 0xFF) << 8 | (blended_b & 0xFF);
-
-                    // Assign the blended pixel to the window buffer
-                    window_buffer[window_pixel_index] = blended_pixel;
-                }
+pub fn draw_sprite_blended(x: i32, y: i32, sprite: &Sprite, blend_mode: BlendMode, window_buffer: &mut [u32], window_width: usize) {
+    let window_height = (window_buffer.len() / window_width.max(1)) as i32;
+
+    for row in 0..sprite.height as i32 {
+        let window_y = y + row;
+        if window_y < 0 || window_y >= window_height {
+            continue;
+        }
+
+        for col in 0..sprite.width as i32 {
+            let window_x = x + col;
+            if window_x < 0 || window_x >= window_width as i32 {
+                continue;
             }
+
+            let sprite_pixel = sprite.data[(row * sprite.width as i32 + col) as usize];
+            let window_pixel_index = window_y as usize * window_width + window_x as usize;
+
+            // Fully transparent pixels are skipped inside blend_pixel
+            window_buffer[window_pixel_index] = blend_pixel(window_buffer[window_pixel_index], sprite_pixel, blend_mode);
         }
     }
 }
@@ -0,0 +1,125 @@
+use crate::graphics::blend::{apply_opacity, blend_pixel, BlendMode};
+use crate::graphics::font::{BitmapFont, TextStyle};
+use crate::graphics::primitives::{draw_shape, Shape};
+use crate::graphics::sprites::{draw_sprite_blended, Sprite};
+
+/// An offscreen pixel buffer which sprites can be drawn into, and which can itself be
+/// composited into another buffer.
+///
+/// Pixels use the same ARGB layout as the window buffer. A freshly created surface is
+/// fully transparent, so only what has been drawn into it shows up when compositing.
+pub struct Surface {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u32>,
+}
+
+impl Surface {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    /// Fills the whole surface with a single ARGB colour. Use `0` to make it transparent again.
+    pub fn clear(&mut self, color: u32) {
+        self.pixels.fill(color);
+    }
+
+    /// Resizes the surface, clearing its contents if the dimensions changed.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        if self.width != width || self.height != height {
+            self.width = width;
+            self.height = height;
+            self.pixels = vec![0; width * height];
+        }
+    }
+
+    /// Draws a sprite into the surface with the given blend mode.
+    pub fn draw_sprite(&mut self, x: i32, y: i32, sprite: &Sprite, blend_mode: BlendMode) {
+        draw_sprite_blended(x, y, sprite, blend_mode, &mut self.pixels, self.width);
+    }
+
+    /// Draws a primitive shape into the surface, in the surface's coordinates.
+    pub fn draw_shape(&mut self, shape: &Shape, color: u32, blend_mode: BlendMode) {
+        draw_shape(shape, color, blend_mode, &mut self.pixels, self.width);
+    }
+
+    /// Draws text into the surface, see `BitmapFont::draw_text` for how it is aligned.
+    pub fn draw_text(&mut self, font: &BitmapFont, text: &str, x: i32, y: i32, style: &TextStyle) {
+        font.draw_text(text, x, y, style, &mut self.pixels, self.width);
+    }
+
+    /// Composites this surface into a target buffer at the given position.
+    ///
+    /// # Parameters
+    /// - `x`: The x-coordinate in the target where the surface's top-left corner is placed.
+    /// - `y`: The y-coordinate in the target where the surface's top-left corner is placed.
+    /// - `opacity`: Multiplier (0.0 - 1.0) applied to every pixel's alpha, used for fades.
+    /// - `blend_mode`: How the surface's pixels are combined with the target's pixels.
+    /// - `target`: The buffer to draw into.
+    /// - `target_width`: The width of the target buffer in pixels.
+    pub fn composite(&self, x: i32, y: i32, opacity: f32, blend_mode: BlendMode, target: &mut [u32], target_width: usize) {
+        if opacity <= 0.0 {
+            return;
+        }
+
+        let target_height = (target.len() / target_width.max(1)) as i32;
+
+        for row in 0..self.height as i32 {
+            let target_y = y + row;
+            if target_y < 0 || target_y >= target_height {
+                continue;
+            }
+
+            for col in 0..self.width as i32 {
+                let target_x = x + col;
+                if target_x < 0 || target_x >= target_width as i32 {
+                    continue;
+                }
+
+                let mut pixel = self.pixels[row as usize * self.width + col as usize];
+                if opacity < 1.0 {
+                    pixel = apply_opacity(pixel, opacity);
+                }
+
+                let target_index = target_y as usize * target_width + target_x as usize;
+                target[target_index] = blend_pixel(target[target_index], pixel, blend_mode);
+            }
+        }
+    }
+}
+
+/// A surface which is only re-rendered when the state it was rendered from changes.
+///
+/// The key describes everything the contents depend on, e.g. which animation frames were
+/// drawn. As long as the key stays the same between frames, the cached pixels are reused.
+pub struct CachedSurface<K: PartialEq> {
+    pub surface: Surface,
+    key: Option<K>,
+}
+
+impl<K: PartialEq> CachedSurface<K> {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            surface: Surface::new(width, height),
+            key: None,
+        }
+    }
+
+    /// Re-renders the surface with `render` if `key` differs from the key of the last render.
+    ///
+    /// Returns `true` if the surface was re-rendered.
+    pub fn update(&mut self, key: K, render: impl FnOnce(&mut Surface)) -> bool {
+        if self.key.as_ref() == Some(&key) {
+            return false;
+        }
+
+        self.surface.clear(0);
+        render(&mut self.surface);
+        self.key = Some(key);
+        true
+    }
+}
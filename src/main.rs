@@ -1,40 +1,58 @@
 use std::fs::File;
 use std::io;
-use std::io::{BufRead, Read};
+use std::io::BufRead;
 use std::path::Path;
+use std::time::Duration;
 
 use minifb::{Window, WindowOptions};
 use winit::event_loop::EventLoop;
 use winit::monitor::MonitorHandle;
 
 use crate::state::player::Player;
-use crate::state::{GameState, Map, Obstacle, ObstacleId, Viewport};
+use crate::state::arena::Arena;
+use crate::state::material::BoxMaterial;
+use crate::state::footsteps::Footsteps;
+use crate::state::metadata::MapMetadata;
+use crate::state::{build_obstacle_grid, GameState, Map, Obstacle, Viewport};
 use crate::{
     graphics::sprites::Sprites,
     state::event_loop::start_event_loop,
     state::input_logic::initialize_input_logic_map,
     state::core_logic::initialize_core_logic_map,
 };
-use rodio::{OutputStream, Sink};
 use crate::graphics::{SCALED_WINDOW_HEIGHT, SCALED_WINDOW_WIDTH};
+use crate::audio::sounds::SoundBank;
+use crate::audio::backend::{AudioBackend, NullBackend, RodioBackend};
+use crate::audio::AudioManager;
+use crate::diagnostics::{log_info, log_warn};
+use crate::graphics::font::BitmapFont;
+use crate::graphics::particles::ParticleSystem;
+use crate::random::Rng;
+use crate::graphics::surface::{CachedSurface, Surface};
 
-mod state;mod graphics;
+mod state;mod graphics;mod diagnostics;mod random;mod audio;
 
 
 
 fn main() {
-    // Initialize the audio output stream and sink
-    let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-    let mut sink = Sink::try_new(&stream_handle).unwrap();
+    // Configure diagnostics from PONDI_LOG / --log before anything is loaded
+    diagnostics::init(diagnostics::Config::from_env_and_args(std::env::args().skip(1)));
+
+    // Open the audio output, which mixes all sounds played during the game
+    let sounds = SoundBank::load("assets/sounds/manifest.txt").unwrap_or_else(|error| {
+        log_warn!(Audio, "Failed to load sounds, playing without sound effects: {}", error);
+        SoundBank::default()
+    });
+    let audio = AudioManager::new(sounds, open_audio_backend(std::env::args().skip(1)));
 
     let sprites = Sprites::new();
     let mut player = Player::new(1.0, 176.0);
     let input_logic = initialize_input_logic_map();
     let core_logic = initialize_core_logic_map();
 
-    let (mut map_one_tiles, map_one_width, map_one_height) = read_grid_from_file("map_one.txt").expect("Failed to read grid from file");
-    let (mut map_two_tiles, _map_two_width, _map_two_height) = read_grid_from_file("map_two.txt").expect("Failed to read grid from file");
-    let (mut map_three_tiles, map_two_width, map_two_height) = read_grid_from_file("map_three.txt").expect("Failed to read grid from file");
+    let (mut map_one_tiles, map_one_width, map_one_height, map_one_metadata) = read_grid_from_file("map_one.txt").expect("Failed to read grid from file");
+    let (mut map_two_tiles, _map_two_width, _map_two_height, map_two_metadata) = read_grid_from_file("map_two.txt").expect("Failed to read grid from file");
+    let (mut map_three_tiles, map_two_width, map_two_height, map_three_metadata) = read_grid_from_file("map_three.txt").expect("Failed to read grid from file");
     let mut map_one_obstacles = extract_obstacles(&map_one_tiles, false);
     let mut map_two_obstacles = extract_obstacles(&map_two_tiles, false);
     let mut map_three_obstacles = extract_obstacles(&map_three_tiles, false);
@@ -68,108 +86,159 @@ fn main() {
     let map_one = Map {
         id: 1,
         tiles: map_one_tiles,
+        grid: build_obstacle_grid(&map_one_obstacles),
         obstacles: &mut map_one_obstacles,
         width: map_one_width,
         height: map_one_height,
         starting_x: 0.0,
         starting_y: 0.0,
         transition_x: 200.0,
-        transition_y: 0.0
+        transition_y: 0.0,
+        metadata: map_one_metadata
     };
 
     let map_two = Map {
         id: 2,
         tiles: map_two_tiles,
+        grid: build_obstacle_grid(&map_two_obstacles),
         obstacles: &mut map_two_obstacles,
         width: map_two_width,
         height: map_two_height,
         starting_x: 0.0,
         starting_y: 0.0,
         transition_x: 200.0,
-        transition_y: 0.0
+        transition_y: 0.0,
+        metadata: map_two_metadata
     };
 
     let map_three = Map {
         id: 3,
         tiles: map_three_tiles,
+        grid: build_obstacle_grid(&map_three_obstacles),
         obstacles: &mut map_three_obstacles,
         width: map_two_width,
         height: map_two_height,
         starting_x: 0.0,
         starting_y: 0.0,
         transition_x: 200.0,
-        transition_y: 0.0
+        transition_y: 0.0,
+        metadata: map_three_metadata
     };
 
     let all_maps = vec![map_one, map_two, map_three];
 
-    let sounds: Vec<Vec<u8>> = load_sounds();
-
     let game_state = GameState {
         player,
         sprites,
+        font: load_font(std::env::args().skip(1)),
         window_buffer: &mut window_buffer,
         grass_sprite_index: 0,
         sky_sprite_index: 0,
         window_width,
         window_height,
-        window: &mut window,
         scaled_buffer: &mut scaled_buffer,
         game_over_index: 0,
         viewport: Viewport::new(window_width as f32, window_height as f32),
         all_maps,
         current_map_index: 0,
-        footstep_index: 0,
-        footstep_active: false,
-        sounds
+        footsteps: Footsteps::new(Rng::from_time()),
+        audio,
+        background_surface: CachedSurface::new(map_one_width, map_one_height),
+        hud_surface: CachedSurface::new(map_one_width, map_one_height),
+        transition_surface: Surface::new(map_one_width, map_one_height),
+        transition_opacity: 0.0,
+        rendered_map_index: 0,
+        explosions: Vec::new(),
+        particles: ParticleSystem::new(Rng::from_time()),
+        debug_overlay: false,
+        frame_time: Duration::ZERO
     };
 
-    start_event_loop(game_state, input_logic, core_logic, &mut sink);
+    start_event_loop(game_state, &mut window, input_logic, core_logic);
 }
 
-fn load_sound(path: &str) -> Vec<u8> {
-    let mut file = File::open(path).expect("Failed to open sounds file");
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer).expect("Failed to read file");
-    buffer
+/// Loads the font given with `--font`, falling back to the built-in font.
+fn load_font(args: impl IntoIterator<Item = String>) -> BitmapFont {
+    let mut args = args.into_iter();
+    let mut path = None;
+    while let Some(arg) = args.next() {
+        if arg == "--font" {
+            path = args.next();
+        }
+    }
+
+    let Some(path) = path else {
+        return BitmapFont::builtin();
+    };
+
+    match BitmapFont::load(&path) {
+        Ok(font) => {
+            log_info!(Assets, "Loaded font {}", path);
+            font
+        }
+        Err(error) => {
+            log_warn!(Assets, "Failed to load font {}, using the built-in font: {}", path, error);
+            BitmapFont::builtin()
+        }
+    }
 }
 
+/// Opens the audio backend chosen with `--audio null` or `--audio device` (the default),
+/// playing silently if there's no sound device.
+fn open_audio_backend(args: impl IntoIterator<Item = String>) -> Box<dyn AudioBackend> {
+    let mut args = args.into_iter();
+    let mut choice = None;
+    while let Some(arg) = args.next() {
+        if arg == "--audio" {
+            choice = args.next();
+        }
+    }
 
-fn load_sounds() -> Vec<Vec<u8>> {
-    let mut sounds = Vec::new();
-    sounds.push(load_sound("assets/sounds/walk_1.wav"));
-    sounds.push(load_sound("assets/sounds/walk_2.wav"));
-    sounds.push(load_sound("assets/sounds/walk_3.wav"));
-    sounds.push(load_sound("assets/sounds/walk_4.wav"));
-    sounds.push(load_sound("assets/sounds/jump.wav"));
-    sounds.push(load_sound("assets/sounds/fall_mild.wav"));
-    sounds.push(load_sound("assets/sounds/fall_heavy.wav"));
-    sounds.push(load_sound("assets/sounds/down.wav"));
-    sounds.push(load_sound("assets/sounds/explosion.wav"));
-    sounds.push(load_sound("assets/sounds/kick.wav"));
-    sounds.push(load_sound("assets/sounds/kick_box.wav"));
-    sounds
+    match choice.as_deref() {
+        Some("null") => return Box::new(NullBackend),
+        None | Some("device") => {}
+        Some(other) => log_warn!(Audio, "Unknown audio backend {}, expected null or device", other),
+    }
+
+    match RodioBackend::open() {
+        Ok(backend) => Box::new(backend),
+        Err(error) => {
+            log_warn!(Audio, "No audio output available, playing without sound: {}", error);
+            Box::new(NullBackend)
+        }
+    }
 }
 
-fn read_grid_from_file(filename: &str) -> io::Result<(Vec<Tile>, usize, usize)> {
+fn read_grid_from_file(filename: &str) -> io::Result<(Vec<Tile>, usize, usize, MapMetadata)> {
     let path = Path::new(filename);
     let file = File::open(&path)?;
     let reader = io::BufReader::new(file);
 
     let mut grid = Vec::new();
+    let mut metadata = MapMetadata::default();
+    let mut y = 0;
 
-    for (y, line) in reader.lines().enumerate() {
+    for line in reader.lines() {
         let line = line?.trim().to_string();
+
+        // Metadata lines such as `@music path` configure the map instead of adding tiles
+        if let Some(directive) = line.strip_prefix('@') {
+            let (key, value) = directive.split_once(char::is_whitespace).unwrap_or((directive, ""));
+            if let Err(error) = metadata.apply(key, value.trim()) {
+                log_warn!(Assets, "Ignoring metadata in {}: {}", filename, error);
+            }
+            continue;
+        }
+
         for (x, c) in line.split_whitespace().enumerate() {
             let x_left = x as f32 * 16.0;
             let x_right = x_left + 16.0;
             let y_bottom = y as f32 * 16.0;
             let y_top = y_bottom - 16.0;
             let tile_type = match c {
-                "X" => TileType::Obstacle,
                 "G" => TileType::Grass,
                 "O" => TileType::Sky,
-                _ => TileType::Unknown,
+                _ => BoxMaterial::from_symbol(c).map_or(TileType::Unknown, TileType::Obstacle),
             };
             grid.push(Tile {
                 tile_type,
@@ -179,6 +248,7 @@ fn read_grid_from_file(filename: &str) -> io::Result<(Vec<Tile>, usize, usize)>
                 y_top,
             });
         }
+        y += 1;
     }
 
     // Automatically detect resolution based on grid size
@@ -186,28 +256,30 @@ fn read_grid_from_file(filename: &str) -> io::Result<(Vec<Tile>, usize, usize)>
         let width = grid.iter().map(|tile| tile.x_right).max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap_or(0.0) as usize;
         let height = grid.iter().map(|tile| tile.y_bottom).max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap_or(0.0) as usize;
 
-        println!("Detected resolution: {}x{}", width, height);
+        log_info!(Assets, "Detected resolution of {}: {}x{}", filename, width, height);
         (width, height)
     } else {
         (0, 0)
     };
 
-    Ok((grid, width, height))
+    Ok((grid, width, height, metadata))
 }
 
-fn extract_obstacles(grid: &Vec<Tile>, sort_by_y: bool) -> Vec<Obstacle> {
-    let mut obstacles = Vec::new();
+fn extract_obstacles(grid: &Vec<Tile>, sort_by_y: bool) -> Arena<Obstacle> {
+    let mut obstacles = Arena::new();
     for tile in grid {
-        if let TileType::Obstacle = tile.tile_type {
-            obstacles.push(Obstacle {
-                id: ObstacleId(obstacles.len()),
+        if let TileType::Obstacle(material) = tile.tile_type {
+            obstacles.insert_with(|id| Obstacle {
+                id,
                 x_left: tile.x_left,
                 x_right: tile.x_right,
                 y_bottom: tile.y_bottom,
                 y_top: tile.y_top,
-                active: true,
-                durability: 2,
+                material,
+                durability: material.durability(),
+                fuse: None,
                 falling: false,
+                velocity_x: 0.0,
                 velocity_y: 0.0
             });
         }
@@ -238,7 +310,7 @@ fn print_grid(grid: &Vec<Tile>) {
         let x = (tile.x_left / 16.0) as usize;
         let y = (tile.y_bottom / 16.0) as usize;
         grid_2d[y][x] = match tile.tile_type {
-            TileType::Obstacle => "X".to_string(),
+            TileType::Obstacle(material) => material.symbol().to_string(),
             TileType::Grass => "G".to_string(),
             TileType::Sky => "O".to_string(),
             TileType::Unknown => "?".to_string(),
@@ -255,7 +327,7 @@ fn print_grid(grid: &Vec<Tile>) {
 /// Searches for obstacles in the grid and outputs their positions.
 fn find_obstacles(grid: &Vec<Tile>) {
     for tile in grid {
-        if let TileType::Obstacle = tile.tile_type {
+        if let TileType::Obstacle(_) = tile.tile_type {
             // println!("Obstacle found: X.LEFT {}, X.RIGHT {}, Y.BOTTOM {}, Y.TOP {}", tile.x_left, tile.x_right, tile.y_bottom, tile.y_top);
         }
     }
@@ -263,7 +335,7 @@ fn find_obstacles(grid: &Vec<Tile>) {
 
 #[derive(Debug)]
 pub enum TileType {
-    Obstacle,
+    Obstacle(BoxMaterial),
     Grass,
     Sky,
     Unknown,
@@ -0,0 +1,91 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A small xorshift random number generator for gameplay and visual variation.
+///
+/// Not suitable for anything which needs good statistical quality, but fast, tiny and
+/// reproducible when created with a fixed seed.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u32,
+}
+
+impl Rng {
+    pub fn new(seed: u32) -> Self {
+        // Xorshift gets stuck at zero
+        Self { state: seed.max(1) }
+    }
+
+    /// Creates a generator seeded from the system clock.
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(1, |time| time.subsec_nanos());
+        Self::new(nanos)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    /// Returns a float in `0.0..1.0`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    /// Returns a float in `min..max`.
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    /// Returns an integer in `min..=max`.
+    pub fn range_u32(&mut self, min: u32, max: u32) -> u32 {
+        if max <= min {
+            return min;
+        }
+        min + self.next_u32() % (max - min + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_gives_the_same_sequence() {
+        let (mut first, mut second) = (Rng::new(12345), Rng::new(12345));
+        for _ in 0..100 {
+            assert_eq!(first.next_u32(), second.next_u32());
+        }
+    }
+
+    #[test]
+    fn zero_seed_does_not_get_stuck() {
+        let mut rng = Rng::new(0);
+        assert_ne!(rng.next_u32(), 0);
+        assert_ne!(rng.next_u32(), rng.next_u32());
+    }
+
+    #[test]
+    fn values_stay_within_their_ranges() {
+        let mut rng = Rng::new(99);
+        for _ in 0..1000 {
+            assert!((0.0..1.0).contains(&rng.next_f32()));
+            assert!((-2.0..3.0).contains(&rng.range(-2.0, 3.0)));
+            assert!((4..=6).contains(&rng.range_u32(4, 6)));
+        }
+        assert_eq!(rng.range_u32(5, 5), 5);
+        assert_eq!(rng.range_u32(5, 2), 5);
+    }
+
+    #[test]
+    fn integer_ranges_reach_both_ends() {
+        let mut rng = Rng::new(7);
+        let values: Vec<u32> = (0..100).map(|_| rng.range_u32(1, 3)).collect();
+        for value in 1..=3 {
+            assert!(values.contains(&value));
+        }
+    }
+}
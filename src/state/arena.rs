@@ -0,0 +1,171 @@
+/// A stable reference to a value in an `Arena`.
+///
+/// Handles stay valid while their value is in the arena, no matter what else is inserted or
+/// removed. Once the value is removed its slot may be reused, but the slot's generation is
+/// bumped, so stale handles to the old value are rejected instead of silently aliasing the new one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Handle {
+    index: u32,
+    generation: u32,
+}
+
+enum Entry<T> {
+    Occupied { generation: u32, value: T },
+    Free { generation: u32 },
+}
+
+/// A generational arena: a vector of slots addressed by `Handle`s instead of indices.
+pub struct Arena<T> {
+    entries: Vec<Entry<T>>,
+    free: Vec<u32>,
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+}
+
+impl<T> Arena<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts a value built from its own handle, for values which store their handle.
+    pub fn insert_with(&mut self, create: impl FnOnce(Handle) -> T) -> Handle {
+        let (index, generation) = match self.free.pop() {
+            Some(index) => match self.entries[index as usize] {
+                Entry::Free { generation } => (index, generation),
+                Entry::Occupied { .. } => unreachable!("free list points at an occupied slot"),
+            },
+            None => {
+                self.entries.push(Entry::Free { generation: 0 });
+                (self.entries.len() as u32 - 1, 0)
+            }
+        };
+
+        let handle = Handle { index, generation };
+        self.entries[index as usize] = Entry::Occupied { generation, value: create(handle) };
+        handle
+    }
+
+    /// Removes the value, invalidating its handle. Returns `None` for stale handles.
+    pub fn remove(&mut self, handle: Handle) -> Option<T> {
+        if !self.contains(handle) {
+            return None;
+        }
+
+        let entry = std::mem::replace(&mut self.entries[handle.index as usize], Entry::Free { generation: handle.generation.wrapping_add(1) });
+        self.free.push(handle.index);
+
+        match entry {
+            Entry::Occupied { value, .. } => Some(value),
+            Entry::Free { .. } => None,
+        }
+    }
+
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.get(handle).is_some()
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        match self.entries.get(handle.index as usize) {
+            Some(Entry::Occupied { generation, value }) if *generation == handle.generation => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, handle: Handle) -> Option<&mut T> {
+        match self.entries.get_mut(handle.index as usize) {
+            Some(Entry::Occupied { generation, value }) if *generation == handle.generation => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Iterates over all values together with their handles, in slot order.
+    pub fn iter(&self) -> impl Iterator<Item = (Handle, &T)> {
+        self.entries.iter().enumerate().filter_map(|(index, entry)| match entry {
+            Entry::Occupied { generation, value } => Some((Handle { index: index as u32, generation: *generation }, value)),
+            Entry::Free { .. } => None,
+        })
+    }
+
+    /// Collects the handles of all values, so the arena can be mutated while walking them.
+    pub fn handles(&self) -> Vec<Handle> {
+        self.iter().map(|(handle, _)| handle).collect()
+    }
+}
+
+impl<T> std::ops::Index<Handle> for Arena<T> {
+    type Output = T;
+
+    fn index(&self, handle: Handle) -> &T {
+        self.get(handle).expect("stale arena handle")
+    }
+}
+
+impl<T> std::ops::IndexMut<Handle> for Arena<T> {
+    fn index_mut(&mut self, handle: Handle) -> &mut T {
+        self.get_mut(handle).expect("stale arena handle")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(arena: &mut Arena<&'static str>, value: &'static str) -> Handle {
+        arena.insert_with(|_| value)
+    }
+
+    #[test]
+    fn handles_stay_valid_around_other_removals() {
+        let mut arena = Arena::new();
+        let first = insert(&mut arena, "first");
+        let second = insert(&mut arena, "second");
+        let third = insert(&mut arena, "third");
+
+        assert_eq!(arena.remove(second), Some("second"));
+        assert_eq!(arena[first], "first");
+        assert_eq!(arena[third], "third");
+        assert_eq!(arena.handles(), vec![first, third]);
+    }
+
+    #[test]
+    fn removed_slots_are_reused_with_a_new_generation() {
+        let mut arena = Arena::new();
+        let old = insert(&mut arena, "old");
+        arena.remove(old);
+        let new = insert(&mut arena, "new");
+
+        assert_eq!(new.index, old.index);
+        assert_ne!(new.generation, old.generation);
+        assert_eq!(arena.iter().count(), 1);
+    }
+
+    #[test]
+    fn stale_handles_are_rejected() {
+        let mut arena = Arena::new();
+        let old = insert(&mut arena, "old");
+        arena.remove(old);
+        let new = insert(&mut arena, "new");
+
+        assert!(!arena.contains(old));
+        assert_eq!(arena.get(old), None);
+        assert_eq!(arena.get_mut(old), None);
+        assert_eq!(arena.remove(old), None);
+        // Removing through the stale handle left the new value alone
+        assert_eq!(arena[new], "new");
+    }
+
+    #[test]
+    fn insert_with_hands_the_value_its_handle() {
+        let mut arena = Arena::new();
+        arena.insert_with(|handle| handle);
+        let handle = arena.insert_with(|handle| handle);
+        assert_eq!(arena[handle], handle);
+    }
+}
@@ -0,0 +1,355 @@
+/// An axis-aligned bounding box in map coordinates, with `y` growing downwards.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Aabb {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self { x, y, width, height }
+    }
+
+    pub fn left(&self) -> f32 {
+        self.x
+    }
+
+    pub fn right(&self) -> f32 {
+        self.x + self.width
+    }
+
+    pub fn top(&self) -> f32 {
+        self.y
+    }
+
+    pub fn bottom(&self) -> f32 {
+        self.y + self.height
+    }
+
+    pub fn center(&self) -> (f32, f32) {
+        (self.x + self.width / 2.0, self.y + self.height / 2.0)
+    }
+
+    pub fn translated(&self, dx: f32, dy: f32) -> Self {
+        Self { x: self.x + dx, y: self.y + dy, ..*self }
+    }
+
+    /// Returns the smallest box containing both boxes.
+    pub fn union(&self, other: &Aabb) -> Self {
+        let left = self.left().min(other.left());
+        let top = self.top().min(other.top());
+        Self::new(left, top, self.right().max(other.right()) - left, self.bottom().max(other.bottom()) - top)
+    }
+
+    /// Returns the box grown by `margin` on every side.
+    pub fn expanded(&self, margin: f32) -> Self {
+        Self::new(self.x - margin, self.y - margin, self.width + margin * 2.0, self.height + margin * 2.0)
+    }
+
+    /// Returns true if the boxes overlap. Boxes which merely touch do not intersect.
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.left() < other.right() && self.right() > other.left() && self.top() < other.bottom() && self.bottom() > other.top()
+    }
+
+    /// Returns true if the boxes share some horizontal span, regardless of their vertical positions.
+    pub fn overlaps_x(&self, other: &Aabb) -> bool {
+        self.left() < other.right() && self.right() > other.left()
+    }
+}
+
+/// A collision box relative to an entity's origin, which is the point its position refers to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hitbox {
+    pub offset_x: f32,
+    pub offset_y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Hitbox {
+    pub const fn new(offset_x: f32, offset_y: f32, width: f32, height: f32) -> Self {
+        Self { offset_x, offset_y, width, height }
+    }
+
+    /// Places the hitbox in the world for an entity at the given origin.
+    pub fn at(&self, x: f32, y: f32) -> Aabb {
+        Aabb::new(x + self.offset_x, y + self.offset_y, self.width, self.height)
+    }
+}
+
+/// Which sides of a box touch something solid.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Contacts {
+    pub ground: bool,
+    pub ceiling: bool,
+    pub wall_left: bool,
+    pub wall_right: bool,
+    /// The ground contact is the floor rather than the top of a solid.
+    pub on_floor: bool,
+}
+
+/// Outcome of moving a box through the world with `move_and_collide`.
+#[derive(Debug, Clone, Copy)]
+pub struct Movement {
+    /// The box after movement and penetration resolution.
+    pub aabb: Aabb,
+    /// Contacts of the box at its final position.
+    pub contacts: Contacts,
+    /// Movement was stopped horizontally by a solid.
+    pub blocked_x: bool,
+    /// Movement was stopped vertically by a solid or the floor.
+    pub blocked_y: bool,
+}
+
+/// The earliest collision found by a swept test.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SweepHit {
+    /// Fraction of the movement (0.0 - 1.0) completed at the moment of impact.
+    pub time: f32,
+    /// Unit normal of the surface which was hit, pointing back towards the moving box.
+    pub normal: (f32, f32),
+}
+
+/// Returns the times at which an interval starts and stops overlapping another interval
+/// while moving by `delta`, as fractions of the movement.
+fn axis_times(min: f32, max: f32, target_min: f32, target_max: f32, delta: f32) -> (f32, f32) {
+    if delta > 0.0 {
+        ((target_min - max) / delta, (target_max - min) / delta)
+    } else if delta < 0.0 {
+        ((target_max - min) / delta, (target_min - max) / delta)
+    } else if max > target_min && min < target_max {
+        // Not moving along this axis, but overlapping for the whole movement
+        (f32::NEG_INFINITY, f32::INFINITY)
+    } else {
+        (f32::INFINITY, f32::NEG_INFINITY)
+    }
+}
+
+/// Sweeps a moving box along `(dx, dy)` against a static target and returns the time of impact.
+///
+/// Unlike testing the box at its final position, this finds collisions anywhere along the
+/// path, so fast boxes can't tunnel through thin ones. Boxes which already overlap at the
+/// start of the movement are not reported, as there is no time of impact to resolve to.
+pub fn sweep_aabb(moving: &Aabb, (dx, dy): (f32, f32), target: &Aabb) -> Option<SweepHit> {
+    if moving.intersects(target) {
+        return None;
+    }
+
+    let (x_entry, x_exit) = axis_times(moving.left(), moving.right(), target.left(), target.right(), dx);
+    let (y_entry, y_exit) = axis_times(moving.top(), moving.bottom(), target.top(), target.bottom(), dy);
+    let entry = x_entry.max(y_entry);
+    let exit = x_exit.min(y_exit);
+
+    if entry >= exit || !(0.0..=1.0).contains(&entry) {
+        return None;
+    }
+
+    let normal = if x_entry > y_entry { (-dx.signum(), 0.0) } else { (0.0, -dy.signum()) };
+    Some(SweepHit { time: entry, normal })
+}
+
+/// Sweeps a moving box against several solids and returns the earliest impact.
+pub fn sweep(moving: &Aabb, delta: (f32, f32), solids: &[Aabb]) -> Option<SweepHit> {
+    solids
+        .iter()
+        .filter_map(|solid| sweep_aabb(moving, delta, solid))
+        .min_by(|a, b| a.time.partial_cmp(&b.time).unwrap())
+}
+
+/// Moves a box by `(dx, dy)`, resolving collisions with `solids` one axis at a time.
+///
+/// The box first moves horizontally, stopping at the time of impact with the first solid
+/// in its way, then moves vertically the same way, so sliding along walls and landing on
+/// top of solids both work without special cases, however fast the box moves. Solids
+/// which already overlap the box, e.g. because they moved into it, are resolved by pushing
+/// the box out. `floor` is the y-coordinate the bottom of the box can never move below.
+pub fn move_and_collide(aabb: Aabb, (dx, dy): (f32, f32), solids: &[Aabb], floor: f32) -> Movement {
+    let mut moved = aabb;
+    let mut blocked_x = false;
+
+    if dx != 0.0 {
+        let hit = sweep(&moved, (dx, 0.0), solids);
+        moved.x += dx * hit.map_or(1.0, |hit| hit.time);
+        blocked_x = hit.is_some();
+    }
+
+    for solid in solids {
+        if moved.intersects(solid) {
+            moved.x = resolve_static(moved.left(), moved.width, solid.left(), solid.right());
+            blocked_x = true;
+        }
+    }
+
+    let mut blocked_y = false;
+
+    if dy != 0.0 {
+        let hit = sweep(&moved, (0.0, dy), solids);
+        moved.y += dy * hit.map_or(1.0, |hit| hit.time);
+        blocked_y = hit.is_some();
+    }
+
+    for solid in solids {
+        if moved.intersects(solid) {
+            moved.y = resolve_static(moved.top(), moved.height, solid.top(), solid.bottom());
+            blocked_y = true;
+        }
+    }
+
+    if moved.bottom() > floor {
+        moved.y = floor - moved.height;
+        blocked_y = true;
+    }
+
+    Movement {
+        aabb: moved,
+        contacts: contacts(&moved, solids, floor),
+        blocked_x,
+        blocked_y,
+    }
+}
+
+/// Resolves a box which overlaps a solid without moving along the axis, by pushing it out
+/// along the axis of least penetration.
+fn resolve_static(start: f32, size: f32, solid_start: f32, solid_end: f32) -> f32 {
+    let push_back = start + size - solid_start;
+    let push_forward = solid_end - start;
+    if push_back < push_forward {
+        solid_start - size
+    } else {
+        solid_end
+    }
+}
+
+/// Computes which sides of the box touch a solid or the floor.
+pub fn contacts(aabb: &Aabb, solids: &[Aabb], floor: f32) -> Contacts {
+    const PROBE: f32 = 0.5;
+
+    let touches = |dx: f32, dy: f32| {
+        let probe = aabb.translated(dx, dy);
+        solids.iter().any(|solid| probe.intersects(solid))
+    };
+
+    let on_floor = aabb.bottom() >= floor - PROBE;
+    Contacts {
+        ground: on_floor || touches(0.0, PROBE),
+        ceiling: touches(0.0, -PROBE),
+        wall_left: touches(-PROBE, 0.0),
+        wall_right: touches(PROBE, 0.0),
+        on_floor,
+    }
+}
+
+/// Returns the distance from the bottom of the box down to the nearest solid top or the floor.
+pub fn distance_to_support(aabb: &Aabb, solids: &[Aabb], floor: f32) -> f32 {
+    solids
+        .iter()
+        .filter(|solid| solid.overlaps_x(aabb) && solid.top() >= aabb.bottom())
+        .map(|solid| solid.top() - aabb.bottom())
+        .fold(floor - aabb.bottom(), f32::min)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLOOR: f32 = 1000.0;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn sweep_finds_the_time_and_side_of_impact() {
+        let moving = Aabb::new(0.0, 0.0, 10.0, 10.0);
+        let wall = Aabb::new(20.0, 0.0, 10.0, 10.0);
+
+        let hit = sweep_aabb(&moving, (20.0, 0.0), &wall).unwrap();
+        assert_close(hit.time, 0.5);
+        assert_eq!(hit.normal, (-1.0, 0.0));
+
+        let floor = Aabb::new(0.0, 15.0, 10.0, 10.0);
+        let hit = sweep_aabb(&moving, (0.0, 10.0), &floor).unwrap();
+        assert_close(hit.time, 0.5);
+        assert_eq!(hit.normal, (0.0, -1.0));
+    }
+
+    #[test]
+    fn sweep_catches_boxes_a_fast_move_would_skip() {
+        let moving = Aabb::new(0.0, 0.0, 4.0, 4.0);
+        let thin = Aabb::new(50.0, 0.0, 1.0, 4.0);
+        // Testing only the final position at x = 100 would miss the thin box entirely
+        assert!(!moving.translated(100.0, 0.0).intersects(&thin));
+        let hit = sweep_aabb(&moving, (100.0, 0.0), &thin).unwrap();
+        assert_close(hit.time, 0.46);
+    }
+
+    #[test]
+    fn sweep_ignores_misses_and_boxes_out_of_reach() {
+        let moving = Aabb::new(0.0, 0.0, 10.0, 10.0);
+        // Passes above the target
+        assert_eq!(sweep_aabb(&moving, (40.0, 0.0), &Aabb::new(20.0, 20.0, 10.0, 10.0)), None);
+        // Stops short of the target
+        assert_eq!(sweep_aabb(&moving, (5.0, 0.0), &Aabb::new(20.0, 0.0, 10.0, 10.0)), None);
+        // Moves away from the target
+        assert_eq!(sweep_aabb(&moving, (-20.0, 0.0), &Aabb::new(20.0, 0.0, 10.0, 10.0)), None);
+        // Already overlapping, there's no time of impact
+        assert_eq!(sweep_aabb(&moving, (5.0, 0.0), &Aabb::new(5.0, 5.0, 10.0, 10.0)), None);
+    }
+
+    #[test]
+    fn sweep_returns_the_earliest_hit() {
+        let moving = Aabb::new(0.0, 0.0, 10.0, 10.0);
+        let solids = [Aabb::new(60.0, 0.0, 10.0, 10.0), Aabb::new(30.0, 0.0, 10.0, 10.0)];
+        let hit = sweep(&moving, (100.0, 0.0), &solids).unwrap();
+        assert_close(hit.time, 0.2);
+        assert_eq!(sweep(&moving, (100.0, 0.0), &[]), None);
+    }
+
+    #[test]
+    fn falling_box_lands_on_a_solid() {
+        let ground = Aabb::new(0.0, 50.0, 32.0, 16.0);
+        let movement = move_and_collide(Aabb::new(8.0, 0.0, 16.0, 16.0), (0.0, 100.0), &[ground], FLOOR);
+        assert_close(movement.aabb.bottom(), 50.0);
+        assert!(movement.blocked_y);
+        assert!(!movement.blocked_x);
+        assert!(movement.contacts.ground);
+        assert!(!movement.contacts.on_floor);
+    }
+
+    #[test]
+    fn diagonal_move_slides_along_a_wall() {
+        let wall = Aabb::new(20.0, -100.0, 10.0, 200.0);
+        let movement = move_and_collide(Aabb::new(0.0, 0.0, 10.0, 10.0), (30.0, 20.0), &[wall], FLOOR);
+        assert_close(movement.aabb.right(), 20.0);
+        assert_close(movement.aabb.top(), 20.0);
+        assert!(movement.blocked_x);
+        assert!(!movement.blocked_y);
+        assert!(movement.contacts.wall_right);
+    }
+
+    #[test]
+    fn boxes_never_sink_below_the_floor() {
+        let movement = move_and_collide(Aabb::new(0.0, 80.0, 10.0, 10.0), (0.0, 50.0), &[], 100.0);
+        assert_close(movement.aabb.bottom(), 100.0);
+        assert!(movement.blocked_y);
+        assert!(movement.contacts.on_floor);
+    }
+
+    #[test]
+    fn overlapping_solids_push_the_box_out_the_short_way() {
+        let solid = Aabb::new(8.0, 0.0, 16.0, 16.0);
+        let movement = move_and_collide(Aabb::new(0.0, 0.0, 10.0, 10.0), (0.0, 0.0), &[solid], FLOOR);
+        assert_close(movement.aabb.right(), 8.0);
+        assert!(movement.blocked_x);
+    }
+
+    #[test]
+    fn support_is_the_nearest_solid_below_or_the_floor() {
+        let aabb = Aabb::new(0.0, 0.0, 16.0, 16.0);
+        let solids = [Aabb::new(0.0, 40.0, 16.0, 16.0), Aabb::new(8.0, 30.0, 16.0, 16.0), Aabb::new(32.0, 20.0, 16.0, 16.0)];
+        assert_close(distance_to_support(&aabb, &solids, 100.0), 14.0);
+        assert_close(distance_to_support(&aabb, &[], 100.0), 84.0);
+    }
+}
@@ -1,86 +1,125 @@
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::io::{BufReader, Cursor};
-use std::rc::Rc;
-use std::thread::sleep;
-use crate::state::{apply_friction, jump_obstacles, Direction, GameState, DOWN_SOUND, GRAVITY, GROUND, LOWER_BOUND, UPPER_BOUND};
-use rodio::{Sink, Source};
-use crate::graphics::renderer::render_pixel_buffer;
-use crate::state::player::Player;
-use crate::state::update::update_pixel_buffer;
-
-
-pub fn execute_core_logic(game_state: &mut GameState, global_commands: &HashMap<String, Rc<RefCell<dyn CoreLogic>>>, sink: &mut Sink, any_key_pressed: bool) {
-    for (_, global_command) in global_commands.iter() {
-        global_command.borrow().execute(game_state, sink);
+use crate::state::{apply_friction, jump_obstacles, GameState, ObstacleId, GRAVITY, GROUND, MAX_FALL_VELOCITY, LOWER_BOUND, UPPER_BOUND};
+use crate::audio::sounds::SoundId;
+use crate::diagnostics::log_debug;
+use crate::state::explosion::update_explosives;
+
+
+pub fn execute_core_logic(game_state: &mut GameState, global_commands: &CoreLogicList, any_key_pressed: bool) {
+    for global_command in global_commands {
+        global_command.execute(game_state);
     }
 
     if !any_key_pressed {
         apply_friction(game_state);
-        sink.stop();
     }
 }
 
 pub trait CoreLogic {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink);
+    fn execute(&self, game_state: &mut GameState);
 }
 
 pub struct ApplyGravity;
 
 impl CoreLogic for ApplyGravity {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
+    fn execute(&self, game_state: &mut GameState) {
         // Apply gravity to the player
         if !game_state.player.on_ground && !game_state.player.on_obstacle {
             game_state.player.vy += GRAVITY;
         }
 
-        let mut obstacle_landed = false;
-
-        // Apply gravity to all obstacles which have falling boolean
-        for obstacle in game_state.all_maps[game_state.current_map_index].obstacles.iter_mut() {
-            if obstacle.active && obstacle.falling {
-                if obstacle.velocity_y >= 16.0 {
-                    // println!("obstacle.velocity_y: {}", obstacle.velocity_y);
-                    obstacle_landed = true;
-                    obstacle.falling = false;
-                } else {
-                    obstacle.y_bottom += GRAVITY * 3.0;
-                    obstacle.y_top += GRAVITY * 3.0;
-                    obstacle.velocity_y += GRAVITY * 3.0;
-                    // println!("obstacle.y_bottom: {}, obstacle.y_top: {}", obstacle.y_bottom, obstacle.y_top);
-                }
+        let map = &mut game_state.all_maps[game_state.current_map_index];
+
+        // Boxes which lost their support, e.g. because the box below was kicked away, start falling
+        for id in map.obstacles.handles() {
+            if !map.obstacles[id].falling && !map.is_supported(id) {
+                log_debug!(Physics, "Box {:?} is falling", id);
+                map.obstacles[id].falling = true;
+                map.obstacles[id].velocity_y = 0.0;
             }
         }
 
-        if obstacle_landed {
-            let file = &game_state.sounds[DOWN_SOUND]; // Get the raw sound data (Vec<u8>)
-            let cursor = Cursor::new(file.clone()); // Clone to create an owned Cursor<Vec<u8>>
+        // Move falling boxes bottom-first, so a box always lands on the final position of the box below it
+        let mut falling: Vec<ObstacleId> = map.obstacles.iter()
+            .filter(|(_, obstacle)| obstacle.falling)
+            .map(|(id, _)| id)
+            .collect();
+        falling.sort_by(|&a, &b| map.obstacles[b].y_bottom.partial_cmp(&map.obstacles[a].y_bottom).unwrap());
+
+        let mut landed = Vec::new();
+        for id in falling {
+            let velocity_y = (map.obstacles[id].velocity_y + GRAVITY).min(MAX_FALL_VELOCITY);
+            map.obstacles[id].velocity_y = velocity_y;
+
+            // Swept, so the box stops on whatever it reaches instead of passing through it
+            if map.drop_obstacle(id, velocity_y) {
+                log_debug!(Physics, "Box {:?} landed", id);
+                map.obstacles[id].falling = false;
+                map.obstacles[id].velocity_y = 0.0;
+                landed.push(map.obstacles[id].aabb().center());
+            }
+        }
+
+        // One landing sound per impact, from where the box landed
+        for (x, y) in landed {
+            game_state.play_sound_at(SoundId::Down, x, y);
+        }
+    }
+}
 
-            let source = rodio::Decoder::new(BufReader::new(cursor))
-                .unwrap()
-                .take_duration(std::time::Duration::from_millis(1000));
+pub struct SlideBoxes;
 
-            sink.append(source); // Play the sound
+impl CoreLogic for SlideBoxes {
+    fn execute(&self, game_state: &mut GameState) {
+        let map = &mut game_state.all_maps[game_state.current_map_index];
 
-            // TODO
-            // Sort obstacles by DESC by y_bottom, meaning the highest obstacles will be put first in the vector (due to polar coordinates)
-            game_state.all_maps[game_state.current_map_index].obstacles.sort_by(|a, b| a.y_bottom.partial_cmp(&b.y_bottom).unwrap());
+        // Kicked boxes slide until friction stops them or they hit another box or the edge of
+        // the map. Boxes sliding off a ledge lose their support and fall in ApplyGravity.
+        for id in map.obstacles.handles() {
+            let velocity_x = map.obstacles[id].velocity_x;
+            if velocity_x == 0.0 {
+                continue;
+            }
+
+            if map.slide_obstacle(id, velocity_x) {
+                log_debug!(Physics, "Box {:?} stopped sliding against an obstacle", id);
+                map.obstacles[id].velocity_x = 0.0;
+            } else if !map.obstacles[id].falling {
+                // Boxes keep their momentum while falling and only slow down on a surface
+                let speed = (velocity_x.abs() - map.obstacles[id].material.friction()).max(0.0);
+                map.obstacles[id].velocity_x = speed * velocity_x.signum();
+            }
         }
     }
 }
 
+pub struct Explosives;
+
+impl CoreLogic for Explosives {
+    fn execute(&self, game_state: &mut GameState) {
+        update_explosives(game_state);
+    }
+}
+
+pub struct UpdateParticles;
+
+impl CoreLogic for UpdateParticles {
+    fn execute(&self, game_state: &mut GameState) {
+        game_state.particles.update();
+    }
+}
+
 pub struct JumpingObstacles;
 
 impl CoreLogic for JumpingObstacles {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
-        jump_obstacles(game_state, sink);
+    fn execute(&self, game_state: &mut GameState) {
+        jump_obstacles(game_state);
     }
 }
 
 pub struct VerticalBounds;
 
 impl CoreLogic for VerticalBounds {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
+    fn execute(&self, game_state: &mut GameState) {
         // Prevent the player from moving out vertical (y) bounds
         if game_state.player.y <= 40.0 {
             game_state.player.on_ground = false;
@@ -92,7 +131,7 @@ impl CoreLogic for VerticalBounds {
 pub struct HorizontalBounds;
 
 impl CoreLogic for HorizontalBounds {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
+    fn execute(&self, game_state: &mut GameState) {
         // Prevent the player from moving out horizontal (x) bounds
         if game_state.player.x < LOWER_BOUND {
             game_state.player.x = LOWER_BOUND;
@@ -105,47 +144,18 @@ impl CoreLogic for HorizontalBounds {
     }
 }
 
-pub struct CheckGameOver;
-
-impl CoreLogic for CheckGameOver {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
-        if game_state.player.game_over {
-            println!("Game Over!");
-
-            for _ in 0..4 {
-                update_pixel_buffer(game_state);
-                render_pixel_buffer(game_state);
-                game_state.game_over_index += 1;
-                sleep(std::time::Duration::from_millis(200));
-            }
-
-            game_state.game_over_index = 0;
-            game_state.player = Player::new(0.0, GROUND); // Reset player state
-        }
-    }
-}
-
-pub struct ApplyFriction;
-
-impl CoreLogic for ApplyFriction {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
-        if game_state.player.direction == Direction::Left {
-            game_state.player.x -= game_state.player.vx;
-        } else {
-            game_state.player.x += game_state.player.vx;
-        }
-        game_state.player.y += game_state.player.vy;
-    }
-}
-
-pub fn initialize_core_logic_map() -> HashMap<String, Rc<RefCell<dyn CoreLogic>>> {
-    let mut logic_map: HashMap<String, Rc<RefCell<dyn CoreLogic>>> = HashMap::new();
-    logic_map.insert("JumpingObstacles".to_string(), Rc::new(RefCell::new(JumpingObstacles)));
-    logic_map.insert("ApplyGravity".to_string(), Rc::new(RefCell::new(ApplyGravity)));
-    logic_map.insert("VerticalBounds".to_string(), Rc::new(RefCell::new(VerticalBounds)));
-    logic_map.insert("HorizontalBounds".to_string(), Rc::new(RefCell::new(HorizontalBounds)));
-    logic_map.insert("CheckGameOver".to_string(), Rc::new(RefCell::new(CheckGameOver)));
-    logic_map.insert("ApplyFriction".to_string(), Rc::new(RefCell::new(ApplyFriction)));
-
-    logic_map
+/// Core logic in the order it runs each frame: boxes settle before anything collides with them.
+/// The event loop checks for a game over once all of it has run
+pub type CoreLogicList = Vec<Box<dyn CoreLogic>>;
+
+pub fn initialize_core_logic_map() -> CoreLogicList {
+    vec![
+        Box::new(ApplyGravity),
+        Box::new(SlideBoxes),
+        Box::new(JumpingObstacles),
+        Box::new(Explosives),
+        Box::new(UpdateParticles),
+        Box::new(VerticalBounds),
+        Box::new(HorizontalBounds),
+    ]
 }
@@ -1,39 +1,39 @@
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::rc::Rc;
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use minifb::Key;
+use minifb::{Key, Window};
 
+use crate::audio::{Bus, Listener};
+use crate::diagnostics::log_info;
 use crate::graphics::renderer::render_pixel_buffer;
-use crate::state::{BACKGROUND_CHANGE_INTERVAL, GameState};
-use crate::state::core_logic::{execute_core_logic, CoreLogic};
+use crate::state::{BACKGROUND_CHANGE_INTERVAL, GameState, GROUND};
+use crate::state::core_logic::{execute_core_logic, CoreLogicList};
 use crate::state::FRAME_DURATION;
 use crate::state::update::update_pixel_buffer;
 use crate::state::input_logic::{handle_user_input, InputLogicMap};
+use crate::state::player::Player;
 
-pub fn start_event_loop(mut game_state: GameState, input_logic_map: InputLogicMap, core_logic_map: HashMap<String, Rc<RefCell<dyn CoreLogic>>>, sink: &mut rodio::Sink) {
+pub fn start_event_loop(mut game_state: GameState, window: &mut Window, input_logic_map: InputLogicMap, core_logic_map: CoreLogicList) {
 
     // Variables for background sprite changing
     let mut last_grass_sprite_index_change = Instant::now();
     let mut last_sky_sprite_index_change = Instant::now();
-    let mut last_footstep_time = Instant::now();
+    let mut last_frame_start = Instant::now();
 
     // Main event loop: runs as long as the window is open and the Escape key is not pressed
-    while game_state.window.is_open() && !game_state.window.is_key_down(Key::Escape) {
+    while window.is_open() && !window.is_key_down(Key::Escape) {
         let start = Instant::now();
-
-        if last_footstep_time.elapsed() >= std::time::Duration::from_millis(500) {
-            game_state.footstep_active = true;
-            last_footstep_time = Instant::now();
-        }
+        game_state.frame_time = start - last_frame_start;
+        last_frame_start = start;
 
         // Handle basic user input, which influence the player's state such as velocity, direction, etc.
-        let any_key_pressed = handle_user_input(&mut game_state, &input_logic_map, sink);
+        let any_key_pressed = handle_user_input(&mut game_state, window, &input_logic_map);
 
         // Process game logic such as obstacle detection, physics, sounds etc.
-        execute_core_logic(&mut game_state, &core_logic_map, sink, any_key_pressed);
+        execute_core_logic(&mut game_state, &core_logic_map, any_key_pressed);
+        if game_state.player.game_over {
+            show_game_over(&mut game_state, window);
+        }
 
         // Change grass sprite every second - alternate between 0 and 1
         if last_grass_sprite_index_change.elapsed() >= BACKGROUND_CHANGE_INTERVAL {
@@ -47,11 +47,25 @@ pub fn start_event_loop(mut game_state: GameState, input_logic_map: InputLogicMa
             last_sky_sprite_index_change = Instant::now(); // Reset the timer to current time
         }
 
+        // Play the current map's music and ambience, crossfading from the previous map's track
+        let map = &game_state.all_maps[game_state.current_map_index];
+        game_state.audio.play_music(map.metadata.music.as_ref());
+        game_state.audio.play_ambience(&map.metadata.ambience);
+        game_state.update_music_layers();
+
+        // Hear positioned sounds from the player's point of view
+        let player = &game_state.player;
+        let (listener_x, listener_y) = player.hitbox.at(player.x, player.y).center();
+        game_state.audio.set_listener(Listener { x: listener_x, y: listener_y });
+
+        // Advance music fades and release the voices of sounds which finished playing
+        game_state.audio.update();
+
         // Update the pixel buffer with the current game state
         update_pixel_buffer(&mut game_state);
 
         // Render the updated buffer
-        render_pixel_buffer(&mut game_state);
+        render_pixel_buffer(&mut game_state, window);
 
         // Maintain a frame rate of 60 fps
         let elapsed = start.elapsed();
@@ -59,4 +73,21 @@ pub fn start_event_loop(mut game_state: GameState, input_logic_map: InputLogicMa
             thread::sleep(FRAME_DURATION - elapsed);
         }
     }
-}
\ No newline at end of file
+}
+
+/// Plays the game over animation, then starts the player over.
+fn show_game_over(game_state: &mut GameState, window: &mut Window) {
+    log_info!(Game, "Game Over!");
+
+    for _ in 0..4 {
+        update_pixel_buffer(game_state);
+        render_pixel_buffer(game_state, window);
+        game_state.game_over_index += 1;
+        thread::sleep(Duration::from_millis(200));
+    }
+
+    game_state.game_over_index = 0;
+    game_state.player = Player::new(0.0, GROUND); // Reset player state
+    // Don't carry sounds of the lost game, e.g. a landing, over into the new one
+    game_state.audio.stop_bus(Bus::Sfx);
+}
@@ -0,0 +1,126 @@
+use crate::diagnostics::{log_debug, log_info};
+use crate::graphics::particles::EmitterConfig;
+use crate::audio::sounds::SoundId;
+use crate::state::{GameState, ObstacleId};
+
+/// Frames between kicking an explosive box and its detonation.
+pub const FUSE_DURATION: u32 = 90;
+/// Frames before an explosive box caught in another explosion detonates, so chain reactions
+/// ripple outwards instead of going off all at once.
+pub const CHAIN_FUSE_DURATION: u32 = 10;
+/// Distance from the centre of an explosion within which boxes and the player are damaged.
+pub const EXPLOSION_RADIUS: f32 = 40.0;
+/// Durability taken from every box caught in an explosion.
+const EXPLOSION_BOX_DAMAGE: u8 = 2;
+/// Health taken from the player when caught in an explosion.
+const EXPLOSION_PLAYER_DAMAGE: u8 = 1;
+/// Frames the explosion effect stays on screen.
+pub const EXPLOSION_EFFECT_FRAMES: u32 = 20;
+
+/// The visual effect of a detonation, which expands and fades out over a few frames.
+#[derive(Debug, Clone, Copy)]
+pub struct Explosion {
+    pub x: f32,
+    pub y: f32,
+    pub frame: u32,
+}
+
+impl Explosion {
+    /// Progress of the effect from 0.0 when it starts to 1.0 when it's gone.
+    pub fn progress(&self) -> f32 {
+        self.frame as f32 / EXPLOSION_EFFECT_FRAMES as f32
+    }
+}
+
+/// Lights the fuse of an explosive box. A fuse which is already burning is only shortened.
+pub fn light_fuse(game_state: &mut GameState, box_id: ObstacleId, frames: u32) {
+    let map = &mut game_state.all_maps[game_state.current_map_index];
+    if let Some(obstacle) = map.obstacles.get_mut(box_id) {
+        if obstacle.material.is_explosive() {
+            obstacle.fuse = Some(obstacle.fuse.map_or(frames, |fuse| fuse.min(frames)));
+            log_debug!(Physics, "Fuse of box {:?} lit, {} frames left", box_id, frames);
+        }
+    }
+}
+
+/// Burns down the fuses of lit boxes, detonating the ones which run out, and advances the
+/// explosion effects.
+pub fn update_explosives(game_state: &mut GameState) {
+    game_state.explosions.retain_mut(|explosion| {
+        explosion.frame += 1;
+        explosion.frame < EXPLOSION_EFFECT_FRAMES
+    });
+
+    let map = &mut game_state.all_maps[game_state.current_map_index];
+    let mut detonating = Vec::new();
+    for id in map.obstacles.handles() {
+        if let Some(fuse) = map.obstacles[id].fuse.as_mut() {
+            *fuse = fuse.saturating_sub(1);
+            if *fuse == 0 {
+                detonating.push(id);
+            }
+        }
+    }
+
+    for id in detonating {
+        detonate(game_state, id);
+    }
+}
+
+/// Blows up an explosive box, damaging the boxes and the player within `EXPLOSION_RADIUS`
+/// and lighting short fuses on explosive boxes nearby.
+pub fn detonate(game_state: &mut GameState, box_id: ObstacleId) {
+    let map = &mut game_state.all_maps[game_state.current_map_index];
+    let Some(obstacle) = map.remove_obstacle(box_id) else {
+        return;
+    };
+    game_state.player.on_obstacles.remove(&box_id);
+
+    let (center_x, center_y) = obstacle.aabb().center();
+    log_info!(Physics, "Box {:?} exploded at {}, {}", box_id, center_x, center_y);
+
+    let within_radius = |(x, y): (f32, f32)| (x - center_x).hypot(y - center_y) <= EXPLOSION_RADIUS;
+
+    // Damage the boxes around the explosion, setting off other explosive boxes
+    let caught: Vec<ObstacleId> = map.obstacles.iter()
+        .filter(|(_, other)| within_radius(other.aabb().center()))
+        .map(|(id, _)| id)
+        .collect();
+
+    let mut chained = Vec::new();
+    for id in caught {
+        let other = &mut map.obstacles[id];
+        if other.material.is_explosive() {
+            chained.push(id);
+        } else if other.material.is_indestructible() {
+            continue;
+        } else if other.durability >= EXPLOSION_BOX_DAMAGE {
+            other.durability -= EXPLOSION_BOX_DAMAGE;
+        } else if let Some(destroyed) = map.remove_obstacle(id) {
+            game_state.player.on_obstacles.remove(&id);
+            let (x, y) = destroyed.aabb().center();
+            game_state.particles.emit(EmitterConfig::debris(destroyed.material.skin()), x, y);
+            log_debug!(Physics, "Box {:?} destroyed by explosion", id);
+        }
+    }
+
+    for id in chained {
+        light_fuse(game_state, id, CHAIN_FUSE_DURATION);
+    }
+
+    // Hurt the player if the centre of its hitbox is caught in the blast
+    let player = &mut game_state.player;
+    if within_radius(player.hitbox.at(player.x, player.y).center()) {
+        player.health = player.health.saturating_sub(EXPLOSION_PLAYER_DAMAGE);
+        log_info!(Game, "Player hit by explosion, health: {}", player.health);
+        if player.health == 0 {
+            player.game_over = true;
+        }
+    }
+
+    game_state.explosions.push(Explosion { x: center_x, y: center_y, frame: 0 });
+    game_state.particles.emit(EmitterConfig::explosion_sparks(), center_x, center_y);
+    game_state.particles.emit(EmitterConfig::explosion_smoke(), center_x, center_y);
+
+    game_state.play_sound_at(SoundId::Explosion, center_x, center_y);
+}
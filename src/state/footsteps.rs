@@ -0,0 +1,120 @@
+use crate::audio::sounds::{PlayParams, SoundId};
+use crate::diagnostics::log_warn;
+use crate::random::Rng;
+use crate::state::material::BoxMaterial;
+use crate::state::player::PlayerState;
+use crate::state::GameState;
+
+/// Walk animation frames on which a foot touches the ground, two per cycle in each direction.
+const FOOTSTEP_FRAMES: [usize; 4] = [0, 2, 4, 6];
+/// Largest random change of a footstep's pitch, so repeated steps don't sound identical.
+const FOOTSTEP_PITCH_VARIATION: f32 = 0.06;
+const FOOTSTEP_VOLUME: f32 = 0.7;
+
+const GRASS_STEPS: [SoundId; 4] = [SoundId::Walk1, SoundId::Walk2, SoundId::Walk3, SoundId::Walk4];
+const BOX_STEPS: [SoundId; 2] = [SoundId::StepBox1, SoundId::StepBox2];
+
+/// What the player is standing on, which decides how footsteps sound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Surface {
+    Grass,
+    Box(BoxMaterial),
+}
+
+impl Surface {
+    /// The surface under the player's feet, or `None` while in the air.
+    pub fn under_player(game_state: &GameState) -> Option<Surface> {
+        let player = &game_state.player;
+        match player.state {
+            PlayerState::OnGround => Some(Surface::Grass),
+            PlayerState::OnObstacle => {
+                let map = &game_state.all_maps[game_state.current_map_index];
+                match player.on_obstacles.iter().find_map(|&id| map.obstacles.get(id)) {
+                    Some(obstacle) => Some(Surface::Box(obstacle.material)),
+                    None => {
+                        log_warn!(Audio, "Player stands on boxes which no longer exist: {:?}", player.on_obstacles);
+                        Some(Surface::Grass)
+                    }
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn sounds(self) -> &'static [SoundId] {
+        match self {
+            Surface::Grass => &GRASS_STEPS,
+            Surface::Box(_) => &BOX_STEPS,
+        }
+    }
+
+    fn pitch(self) -> f32 {
+        match self {
+            Surface::Grass => 1.0,
+            Surface::Box(material) => material.kick_pitch(),
+        }
+    }
+}
+
+/// Cycles through the footstep sounds of each surface, varying their pitch slightly.
+pub struct Footsteps {
+    step: usize,
+    rng: Rng,
+}
+
+impl Footsteps {
+    pub fn new(rng: Rng) -> Self {
+        Self { step: 0, rng }
+    }
+
+    fn next(&mut self, surface: Surface) -> (SoundId, PlayParams) {
+        let sounds = surface.sounds();
+        self.step = (self.step + 1) % sounds.len();
+        let pitch = surface.pitch() * (1.0 + self.rng.range(-FOOTSTEP_PITCH_VARIATION, FOOTSTEP_PITCH_VARIATION));
+        (sounds[self.step], PlayParams::default().volume(FOOTSTEP_VOLUME).pitch(pitch))
+    }
+}
+
+/// Plays a footstep if the walk animation just advanced to a frame where a foot lands.
+pub fn play_footstep(game_state: &mut GameState, frame: usize) {
+    if !FOOTSTEP_FRAMES.contains(&frame) {
+        return;
+    }
+
+    if let Some(surface) = Surface::under_player(game_state) {
+        let (sound, params) = game_state.footsteps.next(surface);
+        game_state.play_sound_with(sound, params);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_cycle_through_the_surface_sounds() {
+        let mut footsteps = Footsteps::new(Rng::new(7));
+        let sounds: Vec<SoundId> = (0..5).map(|_| footsteps.next(Surface::Grass).0).collect();
+        assert_eq!(sounds, [SoundId::Walk2, SoundId::Walk3, SoundId::Walk4, SoundId::Walk1, SoundId::Walk2]);
+    }
+
+    #[test]
+    fn pitch_varies_around_the_material_pitch() {
+        let mut footsteps = Footsteps::new(Rng::new(7));
+        let base = BoxMaterial::Ice.kick_pitch();
+        for _ in 0..100 {
+            let (sound, params) = footsteps.next(Surface::Box(BoxMaterial::Ice));
+            assert!(BOX_STEPS.contains(&sound));
+            assert!((params.pitch - base).abs() <= base * FOOTSTEP_PITCH_VARIATION);
+        }
+    }
+
+    #[test]
+    fn same_seed_plays_the_same_steps() {
+        let mut first = Footsteps::new(Rng::new(42));
+        let mut second = Footsteps::new(Rng::new(42));
+        for _ in 0..10 {
+            assert_eq!(first.next(Surface::Grass).1.pitch, second.next(Surface::Grass).1.pitch);
+        }
+    }
+}
@@ -1,45 +1,62 @@
 use std::collections::HashMap;
-use std::io::{BufReader, Cursor};
 use std::sync::Arc;
-use crate::state::{remove_box, GameState, Obstacle, ACCELERATION, JUMP_SOUND, JUMP_VELOCITY, KICK_BOX_SOUND, KICK_SOUND, MAX_VELOCITY, WALK_SOUND_1, WALK_SOUND_2, WALK_SOUND_3, WALK_SOUND_4};
-use minifb::{Key, KeyRepeat};
-use rodio::{Sink, Source};
-use crate::graphics::sprites::Sprites;
+use crate::state::{kick_box, push_box, remove_box, GameState, Map, ObstacleId, ACCELERATION, JUMP_VELOCITY, MAX_VELOCITY, PUSH_VELOCITY};
+use crate::audio::Bus;
+use crate::audio::sounds::{PlayParams, SoundId};
+use minifb::{Key, KeyRepeat, Window};
+use crate::diagnostics::{log_debug, log_trace, log_warn};
+use crate::state::collision::Aabb;
+use crate::state::explosion::{light_fuse, FUSE_DURATION};
+use crate::state::footsteps::play_footstep;
 use crate::state::Direction::{Left, Right};
 use crate::state::player::Player;
 
-pub fn handle_user_input(game_state: &mut GameState, commands: &InputLogicMap, sink: &mut Sink) -> bool {
+const TOGGLE_KEYS: [Key; 4] = [Key::F3, Key::M, Key::Minus, Key::Equal];
+// Change of the master volume per press of the volume keys
+const MASTER_VOLUME_STEP: f32 = 0.1;
+
+pub fn handle_user_input(game_state: &mut GameState, window: &Window, commands: &InputLogicMap) -> bool {
     let legal_keys = [Key::Space, Key::D, Key::A, Key::X];
     let mut any_key_pressed = false;
 
     for key in legal_keys.iter() {
-        if game_state.window.is_key_pressed(*key, KeyRepeat::Yes) {
+        if window.is_key_pressed(*key, KeyRepeat::Yes) {
             any_key_pressed = true;
-            delegate_command(*key, &commands, game_state, sink);
+            delegate_command(*key, &commands, game_state);
+        }
+    }
+
+    // Toggles only fire once per press and don't count as movement input
+    for key in TOGGLE_KEYS.iter() {
+        if window.is_key_pressed(*key, KeyRepeat::No) {
+            delegate_command(*key, commands, game_state);
         }
     }
 
     any_key_pressed
 }
 
-fn delegate_command(key: Key, commands: &InputLogicMap, game_state: &mut GameState, sink: &mut Sink) {
+fn delegate_command(key: Key, commands: &InputLogicMap, game_state: &mut GameState) {
     if let Some(command) = commands.get(&key) {
-        command.execute(game_state, sink);
+        command.execute(game_state);
     } else {
-        println!("No command associated with key: {:?}", key);
+        log_warn!(Input, "No command associated with key: {:?}", key);
     }
 }
 
 pub trait InputLogic {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink);
+    fn execute(&self, game_state: &mut GameState);
 }
 
 pub struct MoveLeft;
 impl InputLogic for MoveLeft {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
-        let (obstacle_left, _id) = check_collision(game_state.all_maps[game_state.current_map_index].obstacles, &game_state.sprites, &game_state.player, true);
+    fn execute(&self, game_state: &mut GameState) {
+        let (obstacle_left, id) = check_collision(&game_state.all_maps[game_state.current_map_index], &game_state.player, true);
 
-        if !obstacle_left {
+        // Walking into a box pushes it along, if nothing is in its way
+        let pushing = id.is_some_and(|id| push_box(game_state, id, Left));
+
+        if !obstacle_left || pushing {
             game_state.player.obstacle_left = false;
 
             // Update velocity if no collision is detected
@@ -50,6 +67,11 @@ impl InputLogic for MoveLeft {
                 game_state.player.vx *= 0.98;
             }
 
+            // Follow the pushed box at its pace
+            if pushing {
+                game_state.player.vx = game_state.player.vx.min(PUSH_VELOCITY);
+            }
+
             game_state.player.last_key = Some(Key::A);
             game_state.player.direction = Left;
 
@@ -67,6 +89,8 @@ impl InputLogic for MoveLeft {
                         game_state.player.left_increment += 1;
                     }
                 };
+
+                play_footstep(game_state, game_state.player.left_increment);
             }
         } else {
             // Stop the player from moving left if colliding
@@ -81,10 +105,13 @@ impl InputLogic for MoveLeft {
 pub struct MoveRight;
 
 impl InputLogic for MoveRight {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
-        let (obstacle_right, _id) = check_collision(game_state.all_maps[game_state.current_map_index].obstacles, &game_state.sprites, &game_state.player, false);
+    fn execute(&self, game_state: &mut GameState) {
+        let (obstacle_right, id) = check_collision(&game_state.all_maps[game_state.current_map_index], &game_state.player, false);
 
-        if !obstacle_right {
+        // Walking into a box pushes it along, if nothing is in its way
+        let pushing = id.is_some_and(|id| push_box(game_state, id, Right));
+
+        if !obstacle_right || pushing {
             game_state.player.obstacle_right = false;
 
             // Update velocity if no collision is detected
@@ -95,6 +122,11 @@ impl InputLogic for MoveRight {
                 game_state.player.vx *= 0.98;
             }
 
+            // Follow the pushed box at its pace
+            if pushing {
+                game_state.player.vx = game_state.player.vx.min(PUSH_VELOCITY);
+            }
+
             game_state.player.last_key = Some(Key::D);
             game_state.player.direction = Right;
 
@@ -112,76 +144,58 @@ impl InputLogic for MoveRight {
                         game_state.player.right_increment += 1;
                     }
                 }
+
+                play_footstep(game_state, game_state.player.right_increment);
             }
         } else {
             // Stop the player from moving right if colliding
             game_state.player.vx = 0.0;
         }
-
-        if game_state.footstep_active {
-            if game_state.footstep_index == 4 { game_state.footstep_index = 0; }
-            else { game_state.footstep_index += 1; }
-
-            let sound_index = match game_state.footstep_index {
-                0 => WALK_SOUND_1,
-                1 => WALK_SOUND_2,
-                2 => WALK_SOUND_3,
-                _ => WALK_SOUND_4,
-            };
-
-            // let file = &game_state.sounds[sound_index];;
-            // let source = rodio::Decoder::new(BufReader::new(file)).unwrap().take_duration(std::time::Duration::from_millis(125));
-            // let _result = sink.append(source);
-        }
-
-
     }
 }
 
-pub fn check_collision(obstacles: &Vec<Obstacle>, sprites: &Sprites, player: &Player, is_left: bool) -> (bool, Option<usize>) {
-    let mut collision_id: Option<usize> = None;
-    println!("----------------------------------------------------------------------");
-    let collision = obstacles.iter().enumerate().any(|(index, obstacle)| {
-        println!("Checking collision: id: {:?}, x_left: {}, x_right: {}, y_bottom: {}, y_top: {}", obstacle.id, obstacle.x_left, obstacle.x_right, obstacle.y_bottom, obstacle.y_top);
-
-        if obstacle.active == false {
-            println!("- - - - Obstacle is not active - - - -");
-            return false;
-        }
-
-        let player_x = if is_left {
-            player.x + (sprites.player[player.left_increment].width as f32 / 2.5)
-        } else {
-            player.x + (sprites.player[player.right_increment].width as f32 / 1.5)
-        };
+/// How far beyond the player's hitbox `check_collision` looks for obstacles on the facing side.
+pub const COLLISION_REACH: f32 = 2.0;
 
-        if player_x > obstacle.x_left && player_x < obstacle.x_right {
-            println!("Collision of x axis detected: player_x: {}, obstacle.x_left: {}, obstacle.x_right: {}", player_x, obstacle.x_left, obstacle.x_right);
+/// Returns the box `check_collision` tests against obstacles on the given side of the player.
+pub fn collision_probe(player: &Player, is_left: bool) -> Aabb {
+    let hitbox = player.hitbox.at(player.x, player.y);
+    let probe_x = if is_left { hitbox.left() - COLLISION_REACH } else { hitbox.right() };
+    Aabb::new(probe_x, hitbox.top(), COLLISION_REACH, hitbox.height)
+}
 
-            let magica = 25.0;
-            if player.y >= obstacle.y_top + magica && player.y <= obstacle.y_bottom + magica {
-                collision_id = Some(index);
-                println!("Collision detected with obstacle id {:?} x.left {}, x.right: {}, obstacle.y_bottom: {}, obstacle.y_top: {}", obstacle.id, obstacle.x_left, obstacle.x_right , obstacle.y_bottom + magica, obstacle.y_top + magica);
-                true
-            } else {
-                false
-            }
-        } else {
-            false
+/// Checks for an obstacle directly to the left or right of the player.
+///
+/// Returns the id of the obstacle overlapping the probe box the most vertically, as the
+/// probe can span two stacked obstacles.
+pub fn check_collision(map: &Map, player: &Player, is_left: bool) -> (bool, Option<ObstacleId>) {
+    let probe = collision_probe(player, is_left);
+    let mut collision_id: Option<ObstacleId> = None;
+    let mut best_overlap = 0.0;
+
+    // The grid only returns obstacles intersecting the probe
+    for id in map.obstacles_in(&probe) {
+        let aabb = map.obstacles[id].aabb();
+        log_trace!(Physics, "Checking collision: id: {:?}, x_left: {}, x_right: {}, y_bottom: {}, y_top: {}", id, aabb.left(), aabb.right(), aabb.top(), aabb.bottom());
+
+        let overlap = probe.bottom().min(aabb.bottom()) - probe.top().max(aabb.top());
+        if overlap > best_overlap {
+            best_overlap = overlap;
+            collision_id = Some(id);
         }
-    });
+    }
 
     if let Some(id) = collision_id {
-        // println!("Collision detected at x: {}, y: {}, with obstacle id: {}", player.x, player.y, id);
+        log_debug!(Physics, "Collision detected with obstacle id {:?} at x: {}, y: {}", id, player.x, player.y);
     }
 
-    (collision, collision_id)
+    (collision_id.is_some(), collision_id)
 }
 
 pub struct Jump;
 
 impl InputLogic for Jump {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
+    fn execute(&self, game_state: &mut GameState) {
 
         if !game_state.player.is_jumping && (game_state.player.on_ground || game_state.player.on_obstacle) {
             game_state.player.vy = JUMP_VELOCITY;
@@ -191,14 +205,7 @@ impl InputLogic for Jump {
             game_state.player.last_key = Some(Key::Space);
 
 
-            let file = &game_state.sounds[JUMP_SOUND]; // Get the raw sound data (Vec<u8>)
-            let cursor = Cursor::new(file.clone()); // Clone to create an owned Cursor<Vec<u8>>
-
-            let source = rodio::Decoder::new(BufReader::new(cursor))
-                .unwrap()
-                .take_duration(std::time::Duration::from_millis(1000));
-
-            sink.append(source); // Play the sound
+            game_state.play_sound(SoundId::Jump);
 
         }
     }
@@ -207,7 +214,7 @@ impl InputLogic for Jump {
 pub struct Kick;
 
 impl InputLogic for Kick {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
+    fn execute(&self, game_state: &mut GameState) {
         game_state.player.is_kicking = true;
         game_state.player.kick_frame = 0;
         game_state.player.kick_frame_timer = 0;
@@ -215,39 +222,66 @@ impl InputLogic for Kick {
 
         // let sorted_obstacles = sort_obstacles_by_y(game_state.all_maps[game_state.current_map_index].obstacles);
 
-        let (collision, id) = check_collision(game_state.all_maps[game_state.current_map_index].obstacles, &game_state.sprites, &game_state.player, game_state.player.direction == Left);
+        let (_, id) = check_collision(&game_state.all_maps[game_state.current_map_index], &game_state.player, game_state.player.direction == Left);
 
         // Check if the player is adjacent to an obstacle to the right
-        if collision {
+        if let Some(id) = id {
+
+            let obstacle = &game_state.all_maps[game_state.current_map_index].obstacles[id];
+            let (material, (x, y)) = (obstacle.material, obstacle.aabb().center());
+            let kick_params = PlayParams::default().pitch(material.kick_pitch()).at(x, y);
+
+            if material.is_explosive() {
+                game_state.play_sound_with(material.kick_sound(), kick_params);
+                light_fuse(game_state, id, FUSE_DURATION);
+                kick_box(game_state, id, game_state.player.direction);
+            } else if material.is_indestructible() {
+                game_state.play_sound_with(material.kick_sound(), kick_params);
+                log_debug!(Physics, "Kicked indestructible {:?} box", material);
+            } else if game_state.all_maps[game_state.current_map_index].obstacles[id].durability > 0 {
+                game_state.play_sound_with(material.kick_sound(), kick_params);
+                game_state.all_maps[game_state.current_map_index].obstacles[id].durability -= 1;
+                kick_box(game_state, id, game_state.player.direction);
+            } else {
+                // Plays the break sound instead of the kick
+                remove_box(game_state, id);
+            }
 
-            let file = &game_state.sounds[KICK_BOX_SOUND]; // Get the raw sound data (Vec<u8>)
-            let cursor = Cursor::new(file.clone()); // Clone to create an owned Cursor<Vec<u8>>
+        } else {
+            game_state.play_sound(SoundId::Kick);
+        }
+    }
+}
 
-            let source = rodio::Decoder::new(BufReader::new(cursor))
-                .unwrap()
-                .take_duration(std::time::Duration::from_millis(1000));
+pub struct ToggleDebugOverlay;
 
-            sink.append(source); // Play the sound
+impl InputLogic for ToggleDebugOverlay {
+    fn execute(&self, game_state: &mut GameState) {
+        game_state.debug_overlay = !game_state.debug_overlay;
+        game_state.play_ui_sound(SoundId::Toggle);
+    }
+}
 
-            // println!("Player is adjacent to an obstacle with id {} to the right.", id.unwrap());
-            if game_state.all_maps[game_state.current_map_index].obstacles[id.unwrap()].durability > 0 {
-                // println!("Obstacle durability: {}", game_state.all_maps[game_state.current_map_index].obstacles[id.unwrap()].durability);
-                game_state.all_maps[game_state.current_map_index].obstacles[id.unwrap()].durability -= 1;
-            } else {
-                // println!("Obstacle durability: 0");
-                remove_box(game_state, id.unwrap(), sink);
-            }
+pub struct ToggleMusic;
 
-        } else {
-            let file = &game_state.sounds[KICK_SOUND]; // Get the raw sound data (Vec<u8>)
-            let cursor = Cursor::new(file.clone()); // Clone to create an owned Cursor<Vec<u8>>
+impl InputLogic for ToggleMusic {
+    fn execute(&self, game_state: &mut GameState) {
+        let volume = if game_state.audio.bus_volume(Bus::Music) > 0.0 { 0.0 } else { 1.0 };
+        log_debug!(Audio, "Music volume set to {}", volume);
+        game_state.audio.set_bus_volume(Bus::Music, volume);
+        game_state.play_ui_sound(SoundId::Toggle);
+    }
+}
 
-            let source = rodio::Decoder::new(BufReader::new(cursor))
-                .unwrap()
-                .take_duration(std::time::Duration::from_millis(1000));
+/// Raises or lowers the master volume by `MASTER_VOLUME_STEP`, depending on the sign of `.0`.
+pub struct ChangeMasterVolume(f32);
 
-            sink.append(source); // Play the sound
-        }
+impl InputLogic for ChangeMasterVolume {
+    fn execute(&self, game_state: &mut GameState) {
+        let volume = (game_state.audio.master_volume() + self.0 * MASTER_VOLUME_STEP).clamp(0.0, 1.0);
+        log_debug!(Audio, "Master volume set to {}", volume);
+        game_state.audio.set_master_volume(volume);
+        game_state.play_ui_sound(SoundId::Toggle);
     }
 }
 
@@ -260,6 +294,74 @@ pub fn initialize_input_logic_map() -> InputLogicMap {
     logic_map.insert(Key::D, Arc::new(MoveRight));
     logic_map.insert(Key::Space, Arc::new(Jump));
     logic_map.insert(Key::X, Arc::new(Kick));
+    logic_map.insert(Key::F3, Arc::new(ToggleDebugOverlay));
+    logic_map.insert(Key::M, Arc::new(ToggleMusic));
+    logic_map.insert(Key::Minus, Arc::new(ChangeMasterVolume(-1.0)));
+    logic_map.insert(Key::Equal, Arc::new(ChangeMasterVolume(1.0)));
 
     logic_map
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::material::BoxMaterial;
+    use crate::audio::capture::CaptureLog;
+    use crate::state::tests::game_state_with_boxes;
+
+    // Feet of a player standing on the boxes' row, so their sides line up
+    const PLAYER_Y: f32 = 176.0;
+
+    fn played(log: &CaptureLog) -> Vec<SoundId> {
+        log.sounds().iter().map(|sound| sound.id).collect()
+    }
+
+    #[test]
+    fn kicking_a_box_plays_the_box_kick() {
+        let (mut game_state, log) = game_state_with_boxes(&[(1, 10, BoxMaterial::Metal)]);
+        game_state.player = Player::new(0.0, PLAYER_Y);
+
+        Kick.execute(&mut game_state);
+
+        assert!(log.contains(SoundId::KickBox));
+        assert!(!log.contains(SoundId::Kick));
+        let sound = log.sounds()[0];
+        assert_eq!(sound.bus, Bus::Sfx);
+        assert_eq!(sound.frame, 0);
+        assert!(sound.volume > 0.0);
+
+        let map = &game_state.all_maps[0];
+        let (_, obstacle) = map.obstacles.iter().next().unwrap();
+        assert_eq!(obstacle.durability, BoxMaterial::Metal.durability() - 1);
+    }
+
+    #[test]
+    fn breaking_a_box_plays_only_the_break_sound() {
+        let (mut game_state, log) = game_state_with_boxes(&[(1, 10, BoxMaterial::Wood)]);
+        game_state.player = Player::new(0.0, PLAYER_Y);
+        let id = game_state.all_maps[0].obstacles.handles()[0];
+        game_state.all_maps[0].obstacles[id].durability = 0;
+
+        Kick.execute(&mut game_state);
+
+        assert_eq!(played(&log), [SoundId::BoxBreak]);
+        assert!(game_state.all_maps[0].obstacles.iter().next().is_none());
+    }
+
+    #[test]
+    fn kicking_with_no_box_in_reach_plays_the_plain_kick() {
+        let (mut game_state, log) = game_state_with_boxes(&[(3, 10, BoxMaterial::Metal)]);
+        game_state.player = Player::new(0.0, PLAYER_Y);
+
+        Kick.execute(&mut game_state);
+        assert_eq!(played(&log), [SoundId::Kick]);
+
+        // A box behind the player is out of reach too
+        log.clear();
+        game_state.audio.update();
+        game_state.player.x = 3.0 * 16.0 + 12.0;
+        game_state.player.direction = Right;
+        Kick.execute(&mut game_state);
+        assert_eq!(played(&log), [SoundId::Kick]);
+        assert_eq!(log.sounds()[0].frame, 1);
+    }
+}
@@ -0,0 +1,115 @@
+use crate::audio::sounds::SoundId;
+use crate::graphics::sprites::BoxSkin;
+
+/// What a box is made of, selected by its symbol in the map file.
+///
+/// The material decides how many kicks the box takes, how it looks and sounds, and how it
+/// moves when pushed or kicked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BoxMaterial {
+    /// Breaks on the first kick. `W` in map files.
+    Wood,
+    /// Takes three kicks to break. `X` in map files.
+    Metal,
+    /// Can't be broken, pushed or kicked away. `S` in map files.
+    Stone,
+    /// Takes two kicks to break and slides a long way when kicked. `I` in map files.
+    Ice,
+    /// Lights a fuse when kicked and explodes, damaging everything around it. `E` in map files.
+    Explosive,
+}
+
+impl BoxMaterial {
+    /// Parses a map file symbol.
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        match symbol {
+            "W" => Some(BoxMaterial::Wood),
+            "X" => Some(BoxMaterial::Metal),
+            "S" => Some(BoxMaterial::Stone),
+            "I" => Some(BoxMaterial::Ice),
+            "E" => Some(BoxMaterial::Explosive),
+            _ => None,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BoxMaterial::Wood => "W",
+            BoxMaterial::Metal => "X",
+            BoxMaterial::Stone => "S",
+            BoxMaterial::Ice => "I",
+            BoxMaterial::Explosive => "E",
+        }
+    }
+
+    /// Number of kicks a new box absorbs before the next one breaks it.
+    pub fn durability(self) -> u8 {
+        match self {
+            BoxMaterial::Wood => 0,
+            BoxMaterial::Metal => 2,
+            BoxMaterial::Stone => u8::MAX,
+            BoxMaterial::Ice => 1,
+            BoxMaterial::Explosive => 0,
+        }
+    }
+
+    pub fn is_indestructible(self) -> bool {
+        self == BoxMaterial::Stone
+    }
+
+    /// Whether a kick lights a fuse instead of damaging the box.
+    pub fn is_explosive(self) -> bool {
+        self == BoxMaterial::Explosive
+    }
+
+    /// Whether the box can be pushed by walking into it or sent sliding by a kick.
+    pub fn is_movable(self) -> bool {
+        self != BoxMaterial::Stone
+    }
+
+    /// Deceleration per frame of a sliding box resting on something.
+    pub fn friction(self) -> f32 {
+        match self {
+            BoxMaterial::Wood | BoxMaterial::Explosive => 0.2,
+            BoxMaterial::Metal => 0.15,
+            BoxMaterial::Stone => 1.0,
+            BoxMaterial::Ice => 0.02,
+        }
+    }
+
+    /// Sound played when the box is kicked.
+    pub fn kick_sound(self) -> SoundId {
+        match self {
+            BoxMaterial::Wood => SoundId::Kick,
+            BoxMaterial::Metal | BoxMaterial::Ice | BoxMaterial::Explosive => SoundId::KickBox,
+            BoxMaterial::Stone => SoundId::Down,
+        }
+    }
+
+    /// Playback speed of the kick sound, so materials sharing a sound still sound different.
+    pub fn kick_pitch(self) -> f32 {
+        match self {
+            BoxMaterial::Wood => 1.2,
+            BoxMaterial::Metal => 1.0,
+            BoxMaterial::Stone => 0.8,
+            BoxMaterial::Ice => 1.4,
+            BoxMaterial::Explosive => 0.9,
+        }
+    }
+
+    /// How boxes of this material are drawn.
+    pub fn skin(self) -> BoxSkin {
+        match self {
+            BoxMaterial::Wood => BoxSkin::Wood,
+            BoxMaterial::Metal => BoxSkin::Metal,
+            BoxMaterial::Stone => BoxSkin::Stone,
+            BoxMaterial::Ice => BoxSkin::Ice,
+            BoxMaterial::Explosive => BoxSkin::Explosive,
+        }
+    }
+
+    /// Number of kicks a box with the given durability left has taken.
+    pub fn damage(self, durability: u8) -> u8 {
+        self.durability().saturating_sub(durability)
+    }
+}
@@ -0,0 +1,237 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::audio::ambience::{Ambience, AmbienceBed, AmbienceSound};
+use crate::audio::music::MusicTrack;
+
+/// Per-map settings, declared in `@key value` lines above the map's tile grid.
+///
+/// ```text
+/// @title Meadow
+/// @music assets/music/meadow.ogg
+/// @music_intro assets/music/meadow_intro.ogg
+/// @music_layer 4 danger
+/// @fall_damage 48
+/// @ambience assets/sounds/wind.ogg 0.4
+/// @ambience_sound assets/sounds/bird.sfx 3 9 0.5
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MapMetadata {
+    /// Name shown at the top of the screen while the map is played.
+    pub title: Option<String>,
+    /// Music looped while the map is played.
+    pub music: Option<MusicTrack>,
+    /// Channels of a tracker module in `music` which are only heard while their condition holds.
+    pub music_layers: Vec<MusicLayer>,
+    /// Falls from higher than this many pixels hurt the player. `None` makes falls harmless.
+    pub fall_damage: Option<f32>,
+    /// Looping beds and randomly scheduled one-shots which play along with the music.
+    pub ambience: Ambience,
+}
+
+impl MapMetadata {
+    /// Applies a single `@key value` line, with the `@` already stripped.
+    pub fn apply(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "title" => {
+                self.title = Some(value.to_string());
+            }
+            "music" => {
+                self.music = Some(MusicTrack::new(value));
+            }
+            "music_intro" => {
+                let music = self.music.as_mut().ok_or("@music_intro must come after @music")?;
+                music.intro = Some(PathBuf::from(value));
+            }
+            "music_layer" => {
+                let mut values = value.split_whitespace();
+                let channel = values.next().ok_or("@music_layer expects a channel")?;
+                let channel: usize = channel.parse().map_err(|_| format!("{} is not a channel number", channel))?;
+                let condition = values.next().ok_or("@music_layer expects a condition")?;
+                let condition = LayerCondition::from_name(condition)
+                    .ok_or_else(|| format!("unknown music layer condition {}", condition))?;
+                if channel == 0 {
+                    return Err("@music_layer channels start at 1".to_string());
+                }
+                self.music_layers.push(MusicLayer { channel: channel - 1, condition });
+            }
+            "fall_damage" => {
+                self.fall_damage = Some(parse(value)?);
+            }
+            "ambience" => {
+                let mut values = value.split_whitespace();
+                let path = values.next().ok_or("@ambience expects a path")?;
+                let volume = parse_optional(values.next(), 1.0)?;
+                self.ambience.beds.push(AmbienceBed { path: PathBuf::from(path), volume });
+            }
+            "ambience_sound" => {
+                let mut values = value.split_whitespace();
+                let path = values.next().ok_or("@ambience_sound expects a path")?;
+                let min_interval: f32 = parse(values.next().ok_or("@ambience_sound expects a minimum interval")?)?;
+                let max_interval: f32 = parse(values.next().ok_or("@ambience_sound expects a maximum interval")?)?;
+                let volume = parse_optional(values.next(), 1.0)?;
+                if !(0.0..=max_interval).contains(&min_interval) {
+                    return Err(format!("@ambience_sound interval {} to {} is invalid", min_interval, max_interval));
+                }
+                self.ambience.sounds.push(AmbienceSound {
+                    path: PathBuf::from(path),
+                    min_interval: Duration::from_secs_f32(min_interval),
+                    max_interval: Duration::from_secs_f32(max_interval),
+                    volume,
+                });
+            }
+            _ => return Err(format!("unknown key @{}", key)),
+        }
+        Ok(())
+    }
+}
+
+/// A channel of the map's music which is muted unless its condition holds, so the music
+/// picks up with what happens in the game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MusicLayer {
+    /// Index of the channel, starting at 0. Numbered from 1 in the metadata, as in trackers.
+    pub channel: usize,
+    pub condition: LayerCondition,
+}
+
+/// When a music layer is heard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerCondition {
+    /// A fuse is burning or an explosion is on screen.
+    Danger,
+    /// The player has a single point of health left.
+    LowHealth,
+    /// The player is in the air.
+    Airborne,
+}
+
+impl LayerCondition {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "danger" => Some(Self::Danger),
+            "low_health" => Some(Self::LowHealth),
+            "airborne" => Some(Self::Airborne),
+            _ => None,
+        }
+    }
+}
+
+fn parse(value: &str) -> Result<f32, String> {
+    value.parse().map_err(|_| format!("{} is not a number", value))
+}
+
+fn parse_optional(value: Option<&str>, default: f32) -> Result<f32, String> {
+    value.map_or(Ok(default), parse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn music_intro_belongs_to_the_music_above_it() {
+        let mut metadata = MapMetadata::default();
+        metadata.apply("music", "assets/music/towers.wav").unwrap();
+        metadata.apply("music_intro", "assets/music/towers_intro.wav").unwrap();
+        assert_eq!(metadata.music, Some(MusicTrack {
+            path: PathBuf::from("assets/music/towers.wav"),
+            intro: Some(PathBuf::from("assets/music/towers_intro.wav")),
+        }));
+    }
+
+    #[test]
+    fn music_intro_without_music_is_rejected() {
+        let mut metadata = MapMetadata::default();
+        assert_eq!(metadata.apply("music_intro", "assets/music/towers_intro.wav"), Err("@music_intro must come after @music".to_string()));
+        assert_eq!(metadata.music, None);
+    }
+
+    #[test]
+    fn music_layers_are_numbered_from_one() {
+        let mut metadata = MapMetadata::default();
+        metadata.apply("music_layer", "4 danger").unwrap();
+        metadata.apply("music_layer", "1 low_health").unwrap();
+        assert_eq!(metadata.music_layers, vec![
+            MusicLayer { channel: 3, condition: LayerCondition::Danger },
+            MusicLayer { channel: 0, condition: LayerCondition::LowHealth },
+        ]);
+    }
+
+    #[test]
+    fn invalid_music_layers_are_rejected() {
+        let mut metadata = MapMetadata::default();
+        assert!(metadata.apply("music_layer", "0 danger").is_err());
+        assert!(metadata.apply("music_layer", "two danger").is_err());
+        assert!(metadata.apply("music_layer", "2 thunder").is_err());
+        assert!(metadata.apply("music_layer", "2").is_err());
+        assert!(metadata.music_layers.is_empty());
+    }
+
+    #[test]
+    fn fall_damage_is_a_height_in_pixels() {
+        let mut metadata = MapMetadata::default();
+        assert_eq!(metadata.fall_damage, None);
+        metadata.apply("fall_damage", "48").unwrap();
+        assert_eq!(metadata.fall_damage, Some(48.0));
+    }
+
+    #[test]
+    fn fall_damage_must_be_a_number() {
+        let mut metadata = MapMetadata::default();
+        assert_eq!(metadata.apply("fall_damage", "high"), Err("high is not a number".to_string()));
+        assert_eq!(metadata.fall_damage, None);
+    }
+
+    #[test]
+    fn ambience_beds_default_to_full_volume() {
+        let mut metadata = MapMetadata::default();
+        metadata.apply("ambience", "assets/sounds/wind.ogg").unwrap();
+        metadata.apply("ambience", "assets/sounds/rain.ogg 0.4").unwrap();
+        assert_eq!(metadata.ambience.beds, vec![
+            AmbienceBed { path: PathBuf::from("assets/sounds/wind.ogg"), volume: 1.0 },
+            AmbienceBed { path: PathBuf::from("assets/sounds/rain.ogg"), volume: 0.4 },
+        ]);
+    }
+
+    #[test]
+    fn invalid_ambience_beds_are_rejected() {
+        let mut metadata = MapMetadata::default();
+        assert_eq!(metadata.apply("ambience", ""), Err("@ambience expects a path".to_string()));
+        assert_eq!(metadata.apply("ambience", "assets/sounds/wind.ogg loud"), Err("loud is not a number".to_string()));
+        assert!(metadata.ambience.beds.is_empty());
+    }
+
+    #[test]
+    fn ambience_sounds_are_played_every_few_seconds() {
+        let mut metadata = MapMetadata::default();
+        metadata.apply("ambience_sound", "assets/sounds/bird.sfx 3 9 0.5").unwrap();
+        metadata.apply("ambience_sound", "assets/sounds/owl.sfx 2.5 2.5").unwrap();
+        assert_eq!(metadata.ambience.sounds, vec![
+            AmbienceSound {
+                path: PathBuf::from("assets/sounds/bird.sfx"),
+                min_interval: Duration::from_secs(3),
+                max_interval: Duration::from_secs(9),
+                volume: 0.5,
+            },
+            AmbienceSound {
+                path: PathBuf::from("assets/sounds/owl.sfx"),
+                min_interval: Duration::from_secs_f32(2.5),
+                max_interval: Duration::from_secs_f32(2.5),
+                volume: 1.0,
+            },
+        ]);
+    }
+
+    #[test]
+    fn invalid_ambience_sounds_are_rejected() {
+        let mut metadata = MapMetadata::default();
+        assert_eq!(metadata.apply("ambience_sound", ""), Err("@ambience_sound expects a path".to_string()));
+        assert_eq!(metadata.apply("ambience_sound", "assets/sounds/bird.sfx"), Err("@ambience_sound expects a minimum interval".to_string()));
+        assert_eq!(metadata.apply("ambience_sound", "assets/sounds/bird.sfx 3"), Err("@ambience_sound expects a maximum interval".to_string()));
+        assert_eq!(metadata.apply("ambience_sound", "assets/sounds/bird.sfx 3 often"), Err("often is not a number".to_string()));
+        assert_eq!(metadata.apply("ambience_sound", "assets/sounds/bird.sfx 9 3"), Err("@ambience_sound interval 9 to 3 is invalid".to_string()));
+        assert_eq!(metadata.apply("ambience_sound", "assets/sounds/bird.sfx -1 3"), Err("@ambience_sound interval -1 to 3 is invalid".to_string()));
+        assert!(metadata.ambience.sounds.is_empty());
+    }
+}
@@ -1,15 +1,33 @@
-use std::io::{BufReader, Cursor};
+use std::collections::HashSet;
 use std::time::Duration;
 
+use crate::diagnostics::{log_debug, log_info, log_trace};
+use crate::audio::sounds::{PlayParams, SoundId};
+use crate::audio::{AudioManager, Bus};
+use crate::graphics::font::BitmapFont;
+use crate::graphics::particles::{EmitterConfig, ParticleSystem};
 use crate::graphics::sprites::Sprites;
+use crate::graphics::surface::{CachedSurface, Surface};
+use crate::state::arena::{Arena, Handle};
+use crate::state::explosion::Explosion;
+use crate::state::footsteps::Footsteps;
+use crate::state::material::BoxMaterial;
+use crate::state::metadata::{LayerCondition, MapMetadata};
+use crate::state::collision::{distance_to_support, move_and_collide, sweep, Aabb};
+use crate::state::spatial::SpatialGrid;
 use crate::state::player::{Player, PlayerState};
 use crate::Tile;
-use minifb::Window;
-use rodio::Source;
 
 pub mod event_loop;
 pub mod update;
 pub mod player;
+pub mod collision;
+pub mod spatial;
+pub mod arena;
+pub mod material;
+pub mod explosion;
+pub mod footsteps;
+pub mod metadata;
 pub(crate) mod input_logic;
 pub(crate) mod core_logic;
 
@@ -25,18 +43,20 @@ const GROUND: f32 = 205.0;
 const LOWER_BOUND: f32 = 0.0;
 const UPPER_BOUND: f32 = 225.0;
 const KICK_FRAME_DURATION: u32 = 8;
-
-const WALK_SOUND_1: usize = 0;
-const WALK_SOUND_2: usize = 1;
-const WALK_SOUND_3: usize = 2;
-const WALK_SOUND_4: usize = 3;
-const JUMP_SOUND: usize = 4;
-const FALL_MILD_SOUND: usize = 5;
-const FALL_HEAVY_SOUND: usize = 6;
-const DOWN_SOUND: usize = 7;
-const EXPLOSION_SOUND: usize = 8;
-const KICK_SOUND: usize = 9;
-const KICK_BOX_SOUND: usize = 10;
+const OBSTACLE_SIZE: f32 = 16.0;
+// The bottom row of obstacles rests on the ground, level with the player's shadow
+const OBSTACLE_FLOOR: f32 = GROUND + 3.0;
+const MAX_FALL_VELOCITY: f32 = 8.0;
+// Height above the surface below at which the player is considered almost on the ground
+const ALMOST_GROUND_DISTANCE: f32 = 16.0;
+// Falls from higher than this land heavily: two boxes, a little above the ~27 px a jump from flat ground reaches
+const HEAVY_LANDING_HEIGHT: f32 = 32.0;
+// Health taken by a fall from higher than the map's fall damage height
+const FALL_DAMAGE: u8 = 1;
+// Speed at which the player pushes a box by walking into it
+const PUSH_VELOCITY: f32 = 0.5;
+// Initial speed of a box sent sliding by a kick
+const KICK_SLIDE_VELOCITY: f32 = 4.0;
 
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -45,8 +65,9 @@ pub enum Direction {
     Left
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-pub struct ObstacleId(pub usize);
+/// Stable identity of an obstacle. Stays valid across frames while the obstacle exists,
+/// and never refers to a different obstacle once it has been removed.
+pub type ObstacleId = Handle;
 
 #[derive(Clone, Copy)]
 pub struct Obstacle {
@@ -55,97 +76,128 @@ pub struct Obstacle {
     pub x_right: f32,
     pub y_top: f32,
     pub y_bottom: f32,
-    pub velocity_y: f32, // For gravity
+    pub velocity_x: f32, // Horizontal velocity while sliding
+    pub velocity_y: f32, // Downward velocity while falling
     pub falling: bool,   // Whether it's falling
-    pub active: bool,    // If false, box is removed
+    pub material: BoxMaterial,
     pub durability: u8,  // Health of the box
+    pub fuse: Option<u32>, // Frames until an explosive box detonates, once its fuse is lit
 }
-pub fn jump_obstacles(mut game_state: &mut GameState, sink: &mut rodio::Sink) {
-
-    // Apply vertical velocity if jumping
-    if game_state.player.is_jumping {
-        game_state.player.y += game_state.player.vy;
+impl Obstacle {
+    /// The obstacle's collision box. Obstacles are drawn from `y_bottom` downwards, so the
+    /// box spans from `y_bottom` to `y_bottom + 16`.
+    pub fn aabb(&self) -> Aabb {
+        Aabb::new(self.x_left, self.y_bottom, self.x_right - self.x_left, OBSTACLE_SIZE)
     }
+}
 
-    // Check if game_state.player is almost on the ground
-    if game_state.player.y >= 140.0 && game_state.player.y <= 160.0 {
-        game_state.player.almost_ground = true;
-    } else {
-        game_state.player.almost_ground = false;
+/// Builds the broadphase grid for a map's obstacles, keyed by obstacle id.
+pub fn build_obstacle_grid(obstacles: &Arena<Obstacle>) -> SpatialGrid<ObstacleId> {
+    let mut grid = SpatialGrid::new(OBSTACLE_SIZE);
+    for (id, obstacle) in obstacles.iter() {
+        grid.insert(id, &obstacle.aabb());
     }
+    grid
+}
 
-    let mut on_any_obstacle = false;
+/// Moves the player by its velocity, resolving collisions with obstacles and the ground, and
+/// derives the player's state from the resulting contacts.
+pub fn jump_obstacles(game_state: &mut GameState) {
+    let player = &mut game_state.player;
 
-    // Check for each obstacle
-    for obstacle in game_state.all_maps[game_state.current_map_index].obstacles.iter() {
+    // vx holds the speed, the direction holds the sign
+    let dx = if player.direction == Direction::Left { -player.vx } else { player.vx };
+    let hitbox = player.hitbox.at(player.x, player.y);
 
-        if obstacle.active == false {
-            continue;
-        }
+    // Only obstacles around the swept hitbox, and below it down to the floor, can be touched
+    let swept = hitbox.union(&hitbox.translated(dx, player.vy)).expanded(1.0);
+    let area = swept.union(&Aabb::new(swept.x, swept.y, swept.width, GROUND + OBSTACLE_SIZE - swept.y));
+    let solids = game_state.all_maps[game_state.current_map_index].solids_in(&area);
 
-        if game_state.player.x + 10.0 > obstacle.x_left && game_state.player.x + 5.0 < obstacle.x_right {
-            if game_state.player.y <= obstacle.y_bottom && game_state.player.y >= obstacle.y_top {
-                 // println!("game_state.player.y: {}, obstacle.y_bottom: {}, obstacle.y_top: {}", game_state.player.y, obstacle.y_bottom, obstacle.y_top);
-                if game_state.player.state != PlayerState::OnObstacle {
-                    // player just landed on the obstacle
-                    game_state.player.y = obstacle.y_bottom - 1.0;
-                    game_state.player.on_obstacle = true;
-                    game_state.player.on_ground = false;
-                    game_state.player.is_jumping = false;
-                    game_state.player.state = PlayerState::OnObstacle;
-                    game_state.player.vy = 0.0;
-                } else {
-                    // game_state.player is already on the obstacle
-                    game_state.player.on_obstacle = true;
-                    game_state.player.on_ground = false;
-                }
-                on_any_obstacle = true;
-                break;
-            } else if game_state.player.y < obstacle.y_top {
-                // player is above the obstacle but not touching it
-                game_state.player.on_ground = false;
-                game_state.player.on_obstacle = false;
-                game_state.player.above_obstacle = true;
-                game_state.player.state = PlayerState::InAir;
-                game_state.player.is_jumping = true;
-                on_any_obstacle = true;
-                break;
-            }
-        }
+    let movement = move_and_collide(hitbox, (dx, player.vy), &solids, GROUND);
+
+    // The speed the player hits whatever stops the fall with, before the landing zeroes it
+    let impact_velocity = player.vy;
+
+    player.x = movement.aabb.x - player.hitbox.offset_x;
+    player.y = movement.aabb.y - player.hitbox.offset_y;
+    player.contacts = movement.contacts;
+    player.obstacle_left = movement.contacts.wall_left;
+    player.obstacle_right = movement.contacts.wall_right;
+
+    if movement.blocked_x {
+        player.vx = 0.0;
+    }
+    if movement.blocked_y {
+        // Landed on something or bumped into the underside of an obstacle
+        player.vy = 0.0;
     }
 
-    if !on_any_obstacle {
-        if game_state.player.y >= GROUND {
-            // player is on the ground (not on an obstacle)
-            game_state.player.y = GROUND;
-            game_state.player.vy = 0.0;
-            game_state.player.on_ground = true;
-            game_state.player.on_obstacle = false;
-            game_state.player.is_jumping = false;
+    let distance_below = distance_to_support(&movement.aabb, &solids, GROUND);
+    player.above_obstacle = distance_below < GROUND - movement.aabb.bottom();
 
-            if game_state.player.state == PlayerState::InAir {
-                let file = &game_state.sounds[FALL_MILD_SOUND]; // Get the raw sound data (Vec<u8>)
-                let cursor = Cursor::new(file.clone()); // Clone to create an owned Cursor<Vec<u8>>
+    // Remember which obstacles the player stands on, by id, so they can be followed across frames
+    let feet = Aabb::new(movement.aabb.x, movement.aabb.bottom(), movement.aabb.width, 0.5);
+    player.on_obstacles = if movement.contacts.ground && !movement.contacts.on_floor {
+        game_state.all_maps[game_state.current_map_index].obstacles_in(&feet).into_iter().collect()
+    } else {
+        HashSet::new()
+    };
 
-                let source = rodio::Decoder::new(BufReader::new(cursor))
-                    .unwrap()
-                    .take_duration(std::time::Duration::from_millis(1000));
+    let was_in_air = player.state == PlayerState::InAir;
 
-                sink.append(source); // Play the sound
-            }
+    if movement.contacts.ground && player.vy >= 0.0 {
+        player.is_jumping = false;
+        player.almost_ground = false;
+        player.on_ground = movement.contacts.on_floor;
+        player.on_obstacle = !movement.contacts.on_floor;
+        player.state = if movement.contacts.on_floor { PlayerState::OnGround } else { PlayerState::OnObstacle };
 
-            game_state.player.state = PlayerState::OnGround;
+        let fall_height = player.y - player.fall_start_y;
+        player.fall_start_y = player.y;
 
+        if was_in_air {
+            land(game_state, fall_height, impact_velocity);
+        }
+    } else {
+        // player is in the air, either jumping or walked off an obstacle
+        player.on_ground = false;
+        player.on_obstacle = false;
+        player.is_jumping = true;
+        player.almost_ground = distance_below <= ALMOST_GROUND_DISTANCE;
+        player.state = PlayerState::InAir;
+        // Falls are measured from the highest point in the air
+        player.fall_start_y = player.fall_start_y.min(player.y);
+    }
+}
 
-        } else {
-            // player is in the air (not above any obstacle)
-            game_state.player.on_ground = false;
-            game_state.player.on_obstacle = false;
-            game_state.player.above_obstacle = false;
-            game_state.player.state = PlayerState::InAir;
-            game_state.player.is_jumping = true;
+/// Kicks up dust and plays a landing sound, both heavier after a long fall, and applies the
+/// map's fall damage.
+fn land(game_state: &mut GameState, fall_height: f32, impact_velocity: f32) {
+    let player = &mut game_state.player;
+    let feet = player.hitbox.at(player.x, player.y);
+    let heavy = fall_height > HEAVY_LANDING_HEIGHT;
+    log_debug!(Physics, "Player landed after falling {} px at {} px per frame", fall_height, impact_velocity);
+
+    let fall_damage_height = game_state.all_maps[game_state.current_map_index].metadata.fall_damage;
+    if fall_damage_height.is_some_and(|height| fall_height > height) {
+        player.health = player.health.saturating_sub(FALL_DAMAGE);
+        log_info!(Game, "Player hurt by a fall of {} px, health: {}", fall_height, player.health);
+        if player.health == 0 {
+            player.game_over = true;
         }
     }
+
+    let (dust, sound) = if heavy {
+        (EmitterConfig::heavy_landing_dust(), SoundId::FallHeavy)
+    } else {
+        (EmitterConfig::landing_dust(), SoundId::FallMild)
+    };
+    game_state.particles.emit(dust, feet.center().0, feet.bottom());
+
+    // The faster the impact, the louder the landing
+    let volume = (impact_velocity / MAX_FALL_VELOCITY).clamp(0.4, 1.0);
+    game_state.play_sound_with(sound, PlayParams::default().volume(volume));
 }
 
 
@@ -186,83 +238,303 @@ impl Viewport {
     }
 }
 
-fn remove_box(game_state: &mut GameState, box_index: usize, sink: &mut rodio::Sink) {
-    println!("Removing box {}", box_index);
-    let mut to_remove = false;
-    if game_state.all_maps[game_state.current_map_index].obstacles[box_index].active {
-        println!("Box is active");
-        // Obtain the x_left and x_right values of the removed box
-        let removed_box_x_left = game_state.all_maps[game_state.current_map_index].obstacles[box_index].x_left;
-        let removed_box_x_right = game_state.all_maps[game_state.current_map_index].obstacles[box_index].x_right;
-        let removed_box_y_top = game_state.all_maps[game_state.current_map_index].obstacles[box_index].y_top;
-
-        println!("Box x_left: {}, x_right: {}", removed_box_x_left, removed_box_x_right);
-
-        // Remove the box
-
-        println!("Box {} removed", box_index);
-
-        let file = &game_state.sounds[KICK_BOX_SOUND]; // Get the raw sound data (Vec<u8>)
-        let cursor = Cursor::new(file.clone()); // Clone to create an owned Cursor<Vec<u8>>
-
-        let source = rodio::Decoder::new(BufReader::new(cursor))
-            .unwrap()
-            .take_duration(std::time::Duration::from_millis(1000));
-
-        sink.append(source); // Play the sound
-
-        // Shift all boxes above the removed box down by 16 pixels
-        for i in 0..game_state.all_maps[game_state.current_map_index].obstacles.len() {
-            println!("Box id: {}", i);
-
-            let obstacle = &mut game_state.all_maps[game_state.current_map_index].obstacles[i];
-            println!("Box {} x_left: {}, x_right: {}", i, obstacle.x_left, obstacle.x_right);
-            if obstacle.x_left >= removed_box_x_left && obstacle.x_right <= removed_box_x_right { //&& obstacle.y_top < removed_box_y_top {
-                obstacle.falling = true;
-                obstacle.velocity_y = 0.0;
-                println!("Box {} is falling", i);
-                // Remove the box
-                to_remove = true;
-            } else {
-                println!("Box {} is not falling. obs.x_left: {} removed.x_left: {} obs.x_right {} removed.x_right {}", i, obstacle.x_left, removed_box_x_left, obstacle.x_right, removed_box_x_right);
-            }
-        }
+fn remove_box(game_state: &mut GameState, box_id: ObstacleId) {
+    log_debug!(Physics, "Removing box {:?}", box_id);
+    let map = &mut game_state.all_maps[game_state.current_map_index];
+    if let Some(obstacle) = map.remove_obstacle(box_id) {
+        log_trace!(Physics, "Box x_left: {}, x_right: {}", obstacle.x_left, obstacle.x_right);
+
+        // Boxes resting on it lose their support and start falling in ApplyGravity.
+        game_state.player.on_obstacles.remove(&box_id);
+        let (center_x, center_y) = obstacle.aabb().center();
+        game_state.particles.emit(EmitterConfig::debris(obstacle.material.skin()), center_x, center_y);
+        log_debug!(Physics, "Box {:?} removed", box_id);
+
+        game_state.play_sound_with(SoundId::BoxBreak, PlayParams::default().pitch(obstacle.material.kick_pitch()).at(center_x, center_y));
     }
-    if to_remove {
-        game_state.all_maps[game_state.current_map_index].obstacles.remove(box_index);
-        println!("Box {} removed", box_index);
+}
+
+
+/// Pushes a box the player walks into one step in the given direction. Returns true if the
+/// box moved, so the player can follow it.
+fn push_box(game_state: &mut GameState, box_id: ObstacleId, direction: Direction) -> bool {
+    let map = &mut game_state.all_maps[game_state.current_map_index];
+    let obstacle = &map.obstacles[box_id];
+
+    // Boxes which are already on the move, or too heavy to move, can't be pushed
+    if !obstacle.material.is_movable() || obstacle.falling || obstacle.velocity_x != 0.0 {
+        return false;
     }
+
+    let dx = if direction == Direction::Left { -PUSH_VELOCITY } else { PUSH_VELOCITY };
+    let blocked = map.slide_obstacle(box_id, dx);
+    log_trace!(Physics, "Pushing box {:?}, blocked: {}", box_id, blocked);
+    !blocked
 }
 
+/// Sends a kicked box sliding away from the player, unless its material is too heavy to move.
+fn kick_box(game_state: &mut GameState, box_id: ObstacleId, direction: Direction) {
+    let map = &mut game_state.all_maps[game_state.current_map_index];
+    if !map.obstacles[box_id].material.is_movable() {
+        return;
+    }
+
+    map.obstacles[box_id].velocity_x = if direction == Direction::Left { -KICK_SLIDE_VELOCITY } else { KICK_SLIDE_VELOCITY };
+    log_debug!(Physics, "Box {:?} kicked, sliding at {}", box_id, map.obstacles[box_id].velocity_x);
+}
 
 pub struct Map<'a> {
     pub id: usize,
     pub tiles: Vec<Tile>,
-    pub obstacles: &'a mut Vec<Obstacle>,
+    pub obstacles: &'a mut Arena<Obstacle>,
+    pub grid: SpatialGrid<ObstacleId>, // Broadphase over obstacle ids, kept in sync as obstacles move or are removed
     pub width: usize,
     pub height: usize,
     pub starting_x: f32,
     pub starting_y: f32,
     pub transition_x: f32,
-    pub transition_y: f32
+    pub transition_y: f32,
+    pub metadata: MapMetadata
+}
+
+impl Map<'_> {
+    /// Returns the ids of obstacles whose boxes intersect the area.
+    pub fn obstacles_in(&self, area: &Aabb) -> Vec<ObstacleId> {
+        self.grid.query(area)
+            .into_iter()
+            .filter(|&id| self.obstacles[id].aabb().intersects(area))
+            .collect()
+    }
+
+    /// Returns the boxes of obstacles which intersect the area.
+    pub fn solids_in(&self, area: &Aabb) -> Vec<Aabb> {
+        self.obstacles_in(area).into_iter().map(|id| self.obstacles[id].aabb()).collect()
+    }
+
+    /// Moves an obstacle vertically, keeping the grid in sync.
+    pub fn move_obstacle_y(&mut self, id: ObstacleId, dy: f32) {
+        let obstacle = &mut self.obstacles[id];
+        let old = obstacle.aabb();
+        obstacle.y_bottom += dy;
+        obstacle.y_top += dy;
+        self.grid.update(id, &old, &obstacle.aabb());
+    }
+
+    /// Returns true if the obstacle rests on the floor or on top of another obstacle.
+    pub fn is_supported(&self, id: ObstacleId) -> bool {
+        let aabb = self.obstacles[id].aabb();
+        if aabb.bottom() >= OBSTACLE_FLOOR {
+            return true;
+        }
+
+        let below = aabb.translated(0.0, 0.5);
+        self.obstacles_in(&below).into_iter().any(|other| other != id)
+    }
+
+    /// Moves an obstacle horizontally, keeping the grid in sync.
+    pub fn move_obstacle_x(&mut self, id: ObstacleId, dx: f32) {
+        let obstacle = &mut self.obstacles[id];
+        let old = obstacle.aabb();
+        obstacle.x_left += dx;
+        obstacle.x_right += dx;
+        self.grid.update(id, &old, &obstacle.aabb());
+    }
+
+    /// Moves an obstacle sideways by up to `dx`, stopping at the time of impact with the first
+    /// obstacle in its path or the edge of the map. Returns true if it hit something.
+    pub fn slide_obstacle(&mut self, id: ObstacleId, dx: f32) -> bool {
+        let aabb = self.obstacles[id].aabb();
+        let path = aabb.union(&aabb.translated(dx, 0.0));
+        let others: Vec<Aabb> = self.obstacles_in(&path)
+            .into_iter()
+            .filter(|&other| other != id)
+            .map(|other| self.obstacles[other].aabb())
+            .collect();
+
+        let mut distance = sweep(&aabb, (dx, 0.0), &others).map_or(dx, |hit| dx * hit.time);
+        let mut hit = distance.abs() < dx.abs();
+        if aabb.left() + distance <= 0.0 {
+            distance = -aabb.left();
+            hit = true;
+        } else if aabb.right() + distance >= self.width as f32 {
+            distance = self.width as f32 - aabb.right();
+            hit = true;
+        }
+
+        self.move_obstacle_x(id, distance);
+        hit
+    }
+
+    /// Moves an obstacle down by up to `dy`, stopping at the time of impact with the first
+    /// obstacle in its path or the floor. Returns true if it hit something.
+    pub fn drop_obstacle(&mut self, id: ObstacleId, dy: f32) -> bool {
+        let aabb = self.obstacles[id].aabb();
+        let path = aabb.union(&aabb.translated(0.0, dy));
+        let others: Vec<Aabb> = self.obstacles_in(&path)
+            .into_iter()
+            .filter(|&other| other != id)
+            .map(|other| self.obstacles[other].aabb())
+            .collect();
+
+        let mut distance = sweep(&aabb, (0.0, dy), &others).map_or(dy, |hit| dy * hit.time);
+        let mut hit = distance < dy;
+        if aabb.bottom() + distance >= OBSTACLE_FLOOR {
+            distance = OBSTACLE_FLOOR - aabb.bottom();
+            hit = true;
+        }
+
+        self.move_obstacle_y(id, distance);
+        hit
+    }
+
+    /// Removes an obstacle from the map and the grid. Its id, and any copies of it, become
+    /// invalid. Returns `None` if the obstacle was already removed.
+    pub fn remove_obstacle(&mut self, id: ObstacleId) -> Option<Obstacle> {
+        let obstacle = self.obstacles.remove(id)?;
+        self.grid.remove(id, &obstacle.aabb());
+        Some(obstacle)
+    }
 }
 
 pub struct GameState<'a> {
     pub player: Player,
     pub sprites: Sprites,
+    pub font: BitmapFont,
     pub window_buffer: &'a mut Vec<u32>,
     pub grass_sprite_index: usize,
     pub sky_sprite_index: usize,
     pub window_width: usize,
     pub window_height: usize,
-    pub window: &'a mut Window,
     pub scaled_buffer: &'a mut Vec<u32>,
     pub game_over_index: usize,
     pub viewport: Viewport,
     pub all_maps: Vec<Map<'a>>,
     pub current_map_index: usize,
-    pub footstep_index: usize,
-    pub footstep_active: bool,
-    pub sounds: Vec<Vec<u8>> // Store raw sounds data
+    pub footsteps: Footsteps,
+    pub audio: AudioManager,
+    pub background_surface: CachedSurface<(usize, usize, usize)>, // Keyed by map, grass and sky sprite index
+    pub hud_surface: CachedSurface<(usize, u8, Option<usize>)>, // Keyed by map, health and game over frame
+    pub transition_surface: Surface, // Last frame of the previous map, faded out on map change
+    pub transition_opacity: f32,
+    pub rendered_map_index: usize,
+    pub explosions: Vec<Explosion>, // Explosion effects currently on screen
+    pub particles: ParticleSystem,
+    pub debug_overlay: bool,
+    pub frame_time: Duration // Time between the start of the previous frame and the current one
 }
 
+impl GameState<'_> {
+    /// Plays a sound effect, overlapping whatever else is playing.
+    pub fn play_sound(&mut self, sound: SoundId) {
+        self.play_sound_with(sound, PlayParams::default());
+    }
+
+    pub fn play_sound_with(&mut self, sound: SoundId, params: PlayParams) {
+        self.audio.play_sound(Bus::Sfx, sound, params);
+    }
+
+    /// Plays interface feedback, which isn't part of the game world.
+    pub fn play_ui_sound(&mut self, sound: SoundId) {
+        self.audio.play_sound(Bus::Ui, sound, PlayParams::default());
+    }
+
+    /// Plays a sound effect made somewhere in the world, panned and attenuated relative to
+    /// the player.
+    pub fn play_sound_at(&mut self, sound: SoundId, x: f32, y: f32) {
+        self.play_sound_with(sound, PlayParams::default().at(x, y));
+    }
+
+    /// Brings the current map's music layers in and out, muting each one whose condition
+    /// doesn't hold.
+    pub fn update_music_layers(&mut self) {
+        let map = &self.all_maps[self.current_map_index];
+        let player = &self.player;
+        for layer in &map.metadata.music_layers {
+            let heard = match layer.condition {
+                LayerCondition::Danger => !self.explosions.is_empty() || map.obstacles.iter().any(|(_, obstacle)| obstacle.fuse.is_some()),
+                LayerCondition::LowHealth => player.health == 1,
+                LayerCondition::Airborne => !player.on_ground && !player.on_obstacle,
+            };
+            self.audio.set_music_channel_muted(layer.channel, !heard);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::audio::capture::{CaptureBackend, CaptureLog};
+    use crate::audio::sounds::SoundBank;
+    use crate::random::Rng;
+
+    const MAP_SIZE: usize = 256;
+
+    /// A game state on a single map with a box of each material at the given column and row
+    /// of tiles, which records the sound effects it plays in the returned log.
+    pub(crate) fn game_state_with_boxes(boxes: &[(usize, usize, BoxMaterial)]) -> (GameState<'static>, CaptureLog) {
+        let mut obstacles = Arena::new();
+        for &(column, row, material) in boxes {
+            obstacles.insert_with(|id| Obstacle {
+                id,
+                x_left: column as f32 * 16.0,
+                x_right: column as f32 * 16.0 + 16.0,
+                y_bottom: row as f32 * 16.0,
+                y_top: row as f32 * 16.0 - 16.0,
+                material,
+                durability: material.durability(),
+                fuse: None,
+                falling: false,
+                velocity_x: 0.0,
+                velocity_y: 0.0,
+            });
+        }
+
+        // The game state borrows its obstacles and buffers, which live as long as the test
+        let obstacles = Box::leak(Box::new(obstacles));
+        let map = Map {
+            id: 1,
+            tiles: Vec::new(),
+            grid: build_obstacle_grid(obstacles),
+            obstacles,
+            width: MAP_SIZE,
+            height: MAP_SIZE,
+            starting_x: 0.0,
+            starting_y: 0.0,
+            transition_x: 200.0,
+            transition_y: 0.0,
+            metadata: MapMetadata::default(),
+        };
+
+        let backend = CaptureBackend::new();
+        let log = backend.log();
+        let sounds = SoundBank::load("assets/sounds/manifest.txt").unwrap();
+
+        let game_state = GameState {
+            player: Player::new(0.0, GROUND),
+            sprites: Sprites::new(),
+            font: BitmapFont::builtin(),
+            window_buffer: Box::leak(Box::new(vec![0; MAP_SIZE * MAP_SIZE])),
+            grass_sprite_index: 0,
+            sky_sprite_index: 0,
+            window_width: MAP_SIZE,
+            window_height: MAP_SIZE,
+            scaled_buffer: Box::leak(Box::new(vec![0; MAP_SIZE * MAP_SIZE])),
+            game_over_index: 0,
+            viewport: Viewport::new(MAP_SIZE as f32, MAP_SIZE as f32),
+            all_maps: vec![map],
+            current_map_index: 0,
+            footsteps: Footsteps::new(Rng::new(1)),
+            audio: AudioManager::new(sounds, Box::new(backend)),
+            background_surface: CachedSurface::new(MAP_SIZE, MAP_SIZE),
+            hud_surface: CachedSurface::new(MAP_SIZE, MAP_SIZE),
+            transition_surface: Surface::new(MAP_SIZE, MAP_SIZE),
+            transition_opacity: 0.0,
+            rendered_map_index: 0,
+            explosions: Vec::new(),
+            particles: ParticleSystem::new(Rng::new(1)),
+            debug_overlay: false,
+            frame_time: Duration::ZERO,
+        };
+        (game_state, log)
+    }
+}
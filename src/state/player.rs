@@ -3,6 +3,7 @@ use std::collections::HashSet;
 use minifb::Key;
 
 use crate::state::{Direction, ObstacleId};
+use crate::state::collision::{Contacts, Hitbox};
 use crate::state::Direction::Right;
 use crate::state::player::PlayerState::OnGround;
 
@@ -17,6 +18,13 @@ pub enum PlayerState {
     Idle,
     OnObstacle
 }
+
+/// Health the player starts with. Explosions take health away, and the game is over at zero.
+pub const PLAYER_MAX_HEALTH: u8 = 3;
+
+/// The player's collision box relative to (x, y), where y is the position of the player's feet.
+pub const PLAYER_HITBOX: Hitbox = Hitbox::new(5.0, -24.0, 11.0, 24.0);
+
     pub struct Player {
         pub x: f32,
         pub y: f32,
@@ -43,7 +51,11 @@ pub enum PlayerState {
         pub above_obstacle: bool,
         pub current_map: usize,
         pub spike_active: bool,
-        pub game_over: bool
+        pub game_over: bool,
+        pub health: u8,
+        pub fall_start_y: f32, // Highest point of the current fall, or the feet while on the ground
+        pub hitbox: Hitbox,
+        pub contacts: Contacts
     }
 
 impl Player {
@@ -74,7 +86,11 @@ impl Player {
             above_obstacle: false,
             current_map: 1,
             spike_active: false,
-            game_over: false
+            game_over: false,
+            health: PLAYER_MAX_HEALTH,
+            fall_start_y: y,
+            hitbox: PLAYER_HITBOX,
+            contacts: Contacts::default()
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+
+use crate::state::collision::Aabb;
+
+/// A uniform grid which buckets entity ids by the cells their bounding boxes overlap, so
+/// collision queries only have to look at entities near the area of interest.
+///
+/// The grid doesn't store the boxes themselves. Callers insert an entity with its current
+/// box, and must call `update` whenever the box moves and `remove` when the entity goes away.
+pub struct SpatialGrid<Id> {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<Id>>,
+}
+
+impl<Id: Copy + Ord> SpatialGrid<Id> {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    /// Returns the inclusive range of cells covered by the box, as `(min_x, min_y, max_x, max_y)`.
+    ///
+    /// The right and bottom edges are exclusive, so a 16x16 box aligned to a 16 pixel cell
+    /// only occupies that one cell.
+    fn cell_range(&self, aabb: &Aabb) -> (i32, i32, i32, i32) {
+        let min_x = (aabb.left() / self.cell_size).floor() as i32;
+        let min_y = (aabb.top() / self.cell_size).floor() as i32;
+        let max_x = ((aabb.right() / self.cell_size).ceil() as i32 - 1).max(min_x);
+        let max_y = ((aabb.bottom() / self.cell_size).ceil() as i32 - 1).max(min_y);
+        (min_x, min_y, max_x, max_y)
+    }
+
+    fn cells_of(&self, aabb: &Aabb) -> impl Iterator<Item = (i32, i32)> {
+        let (min_x, min_y, max_x, max_y) = self.cell_range(aabb);
+        (min_y..=max_y).flat_map(move |y| (min_x..=max_x).map(move |x| (x, y)))
+    }
+
+    pub fn insert(&mut self, id: Id, aabb: &Aabb) {
+        for cell in self.cells_of(aabb).collect::<Vec<_>>() {
+            self.cells.entry(cell).or_default().push(id);
+        }
+    }
+
+    /// Removes an entity, given the box it was last inserted or updated with.
+    pub fn remove(&mut self, id: Id, aabb: &Aabb) {
+        for cell in self.cells_of(aabb).collect::<Vec<_>>() {
+            if let Some(ids) = self.cells.get_mut(&cell) {
+                ids.retain(|other| *other != id);
+                if ids.is_empty() {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+    }
+
+    /// Moves an entity from its old box to its new one. Cheap if it stays in the same cells.
+    pub fn update(&mut self, id: Id, old: &Aabb, new: &Aabb) {
+        if self.cell_range(old) != self.cell_range(new) {
+            self.remove(id, old);
+            self.insert(id, new);
+        }
+    }
+
+    /// Returns the ids of all entities in cells overlapping the area, each id once and in
+    /// ascending order. The entities' boxes still need to be tested against the area.
+    pub fn query(&self, area: &Aabb) -> Vec<Id> {
+        let mut ids: Vec<Id> = self.cells_of(area)
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile(column: f32, row: f32) -> Aabb {
+        Aabb::new(column * 16.0, row * 16.0, 16.0, 16.0)
+    }
+
+    #[test]
+    fn aligned_boxes_occupy_a_single_cell() {
+        let grid: SpatialGrid<u32> = SpatialGrid::new(16.0);
+        assert_eq!(grid.cell_range(&tile(2.0, 3.0)), (2, 3, 2, 3));
+        assert_eq!(grid.cell_range(&Aabb::new(8.0, 8.0, 16.0, 16.0)), (0, 0, 1, 1));
+    }
+
+    #[test]
+    fn query_returns_nearby_ids_once_in_order() {
+        let mut grid = SpatialGrid::new(16.0);
+        grid.insert(3, &Aabb::new(8.0, 8.0, 16.0, 16.0));
+        grid.insert(1, &tile(0.0, 0.0));
+        grid.insert(2, &tile(10.0, 10.0));
+
+        assert_eq!(grid.query(&Aabb::new(0.0, 0.0, 32.0, 32.0)), vec![1, 3]);
+        assert_eq!(grid.query(&tile(10.0, 10.0)), vec![2]);
+        assert!(grid.query(&tile(5.0, 5.0)).is_empty());
+    }
+
+    #[test]
+    fn updated_and_removed_ids_leave_their_old_cells() {
+        let mut grid = SpatialGrid::new(16.0);
+        grid.insert(1, &tile(0.0, 0.0));
+        grid.update(1, &tile(0.0, 0.0), &tile(4.0, 0.0));
+        assert!(grid.query(&tile(0.0, 0.0)).is_empty());
+        assert_eq!(grid.query(&tile(4.0, 0.0)), vec![1]);
+
+        grid.remove(1, &tile(4.0, 0.0));
+        assert!(grid.query(&tile(4.0, 0.0)).is_empty());
+        assert!(grid.cells.is_empty());
+    }
+
+    #[test]
+    fn negative_coordinates_have_their_own_cells() {
+        let mut grid = SpatialGrid::new(16.0);
+        grid.insert(1, &tile(-1.0, -1.0));
+        assert!(grid.query(&tile(0.0, 0.0)).is_empty());
+        assert_eq!(grid.query(&Aabb::new(-4.0, -4.0, 2.0, 2.0)), vec![1]);
+    }
+}
@@ -1,20 +1,138 @@
-use crate::graphics::sprites::draw_sprite;
+use crate::graphics::blend::BlendMode;
+use crate::graphics::debug_overlay::queue_debug_overlay;
+use crate::graphics::font::{Align, TextStyle};
+use crate::graphics::primitives::Shape;
+use crate::graphics::render_queue::{Layer, RenderQueue};
+use crate::graphics::surface::Surface;
 use crate::state::Direction::{Left, Right};
+use crate::state::explosion::{EXPLOSION_RADIUS, FUSE_DURATION};
+use crate::state::player::PLAYER_MAX_HEALTH;
 use crate::state::*;
 
+const TRANSITION_FADE_STEP: f32 = 0.05;
+const EXPLOSION_OUTER_COLOR: u32 = 0xFF6010;
+const EXPLOSION_INNER_COLOR: u32 = 0xFFE080;
+const HEALTH_COLOR: u32 = 0xFFE03030;
+const HEALTH_SIZE: i32 = 6;
+const HUD_TEXT_COLOR: u32 = 0xFFFFFFFF;
+
 pub fn update_pixel_buffer(game_state: &mut GameState) {
-    draw_game_world(game_state);
-    draw_player(game_state)
+    // Advance animations before borrowing the sprites for the render queue
+    advance_kick_animation(game_state);
+
+    // Render the offscreen surfaces which are composed into the final frame
+    render_background_surface(game_state);
+    render_hud_surface(game_state);
+    begin_map_transition(game_state);
+
+    // The buffer is moved out while queuing, as the queued sprites borrow the game state
+    let map_width = game_state.all_maps[game_state.current_map_index].width;
+    let mut window_buffer = std::mem::take(game_state.window_buffer);
+
+    let mut render_queue = RenderQueue::new();
+    queue_game_world(game_state, &mut render_queue);
+    queue_player(game_state, &mut render_queue);
+    queue_effects(game_state, &mut render_queue);
+    game_state.particles.queue(&game_state.sprites, &mut render_queue);
+    queue_surfaces(game_state, &mut render_queue);
+    queue_debug_overlay(game_state, &mut render_queue);
+
+    // Draw everything queued this frame, ordered by layer and z value
+    render_queue.flush(&mut window_buffer, map_width);
+    *game_state.window_buffer = window_buffer;
+
+    // Fade out the previous map's last frame
+    game_state.transition_opacity = (game_state.transition_opacity - TRANSITION_FADE_STEP).max(0.0);
 }
 
-fn draw_player(game_state: &mut GameState) {
+/// Renders the static background into its cached surface, which is only redrawn when the
+/// grass or sky animation frame, or the map, changes.
+fn render_background_surface(game_state: &mut GameState) {
+    let map = &game_state.all_maps[game_state.current_map_index];
+    let (map_width, map_height) = (map.width, map.height);
+    let key = (game_state.current_map_index, game_state.grass_sprite_index, game_state.sky_sprite_index);
+    let sprites = &game_state.sprites;
 
-    // Determine the current direction and action of the player
-    let direction = game_state.player.direction;
+    game_state.background_surface.update(key, |surface| {
+        surface.resize(map_width, map_height);
 
-    // Determine the sprite to draw
-    let sprite_to_draw =
+        // First draw the blue background
+        surface.draw_sprite(0, 0, &sprites.blue_background[0], BlendMode::Alpha);
+
+        // Then draw the grass, which alternates between two sprites to emulate wind
+        let grass_y = (map_height - sprites.grass[0].height as usize) as i32;
+        surface.draw_sprite(0, grass_y, &sprites.grass[key.1], BlendMode::Alpha);
+
+        // Then draw the sky, which alternates between four sprites to emulate clouds
+        surface.draw_sprite(0, 0, &sprites.sky[key.2], BlendMode::Alpha);
+    });
+}
+
+/// Renders the HUD into its cached surface, which is composed on top of the world independently
+/// of it and only redrawn when the map, the player's health or the game over frame changes.
+fn render_hud_surface(game_state: &mut GameState) {
+    let map = &game_state.all_maps[game_state.current_map_index];
+    let (map_width, map_height) = (map.width, map.height);
+    let title = map.metadata.title.as_deref();
+    let player = &game_state.player;
+    let game_over_frame = player.game_over.then_some(game_state.game_over_index);
+    let key = (game_state.current_map_index, player.health, game_over_frame);
+    let (sprites, font) = (&game_state.sprites, &game_state.font);
+
+    game_state.hud_surface.update(key, |surface| {
+        surface.resize(map_width, map_height);
+
+        if let Some(frame) = game_over_frame {
+            surface.draw_sprite(0, 0, &sprites.game_over[frame], BlendMode::Alpha);
+        }
+
+        // The player's health, as a labelled row of squares in the top right corner
+        let health_width = PLAYER_MAX_HEALTH as i32 * (HEALTH_SIZE + 2);
+        for index in 0..PLAYER_MAX_HEALTH {
+            let position = (map_width as i32 - (index as i32 + 1) * (HEALTH_SIZE + 2), 2);
+            let filled = index < player.health;
+            let square = Shape::Rect { position, size: (HEALTH_SIZE, HEALTH_SIZE), filled };
+            surface.draw_shape(&square, HEALTH_COLOR, BlendMode::Alpha);
+        }
+        let label_style = TextStyle::new(HUD_TEXT_COLOR).align(Align::Right);
+        surface.draw_text(font, "HP", map_width as i32 - health_width - 2, 1, &label_style);
+
+        // The map's title, centred and wrapped so it stays clear of the health
+        if let Some(title) = title {
+            let title_style = TextStyle::new(HUD_TEXT_COLOR).align(Align::Center).max_width(map_width as i32 - 2 * (health_width + 16));
+            surface.draw_text(font, title, map_width as i32 / 2, 2, &title_style);
+        }
+    });
+}
+
+/// Captures the last rendered frame when the map changes, so it can be faded out over the new map.
+fn begin_map_transition(game_state: &mut GameState) {
+    if game_state.rendered_map_index == game_state.current_map_index {
+        return;
+    }
+
+    let previous_map = &game_state.all_maps[game_state.rendered_map_index];
+    let mut snapshot = Surface::new(previous_map.width, previous_map.height);
+    let len = snapshot.pixels.len().min(game_state.window_buffer.len());
+    snapshot.pixels[..len].copy_from_slice(&game_state.window_buffer[..len]);
+
+    game_state.transition_surface = snapshot;
+    game_state.transition_opacity = 1.0;
+    game_state.rendered_map_index = game_state.current_map_index;
+}
+
+fn queue_surfaces<'s>(game_state: &'s GameState, render_queue: &mut RenderQueue<'s>) {
+    render_queue.push_surface(Layer::Background, 0, 0, 0, &game_state.background_surface.surface, 1.0, BlendMode::Alpha);
+
+    // The transition sits below the HUD, so the HUD stays readable while the maps crossfade
+    if game_state.transition_opacity > 0.0 {
+        render_queue.push_surface(Layer::Hud, -1, 0, 0, &game_state.transition_surface, game_state.transition_opacity, BlendMode::Alpha);
+    }
+
+    render_queue.push_surface(Layer::Hud, 0, 0, 0, &game_state.hud_surface.surface, 1.0, BlendMode::Alpha);
+}
 
+fn advance_kick_animation(game_state: &mut GameState) {
     if game_state.player.is_kicking {
         game_state.player.kick_frame_timer += 1;
         if game_state.player.kick_frame_timer >= KICK_FRAME_DURATION as usize {
@@ -26,7 +144,18 @@ fn draw_player(game_state: &mut GameState) {
                 game_state.player.kick_frame = 0;
             }
         }
+    }
+}
+
+fn queue_player<'s>(game_state: &'s GameState, render_queue: &mut RenderQueue<'s>) {
+
+    // Determine the current direction and action of the player
+    let direction = game_state.player.direction;
+
+    // Determine the sprite to draw
+    let sprite_to_draw =
 
+    if game_state.player.is_kicking {
         // Select the correct kick frame based on direction
         if direction == Right {
             &game_state.sprites.kick[game_state.player.kick_frame]
@@ -51,13 +180,14 @@ fn draw_player(game_state: &mut GameState) {
     };
 
 
-    // Draw the chosen player sprite
-    draw_sprite(
-        game_state.player.x as usize,
-        game_state.player.y as usize - (sprite_to_draw.height - 3) as usize,
+    // Queue the chosen player sprite
+    render_queue.push_sprite(
+        Layer::Entities,
+        0,
+        game_state.player.x as i32,
+        game_state.player.y as i32 - (sprite_to_draw.height as i32 - 3),
         sprite_to_draw,
-        game_state.window_buffer,
-        game_state.all_maps[game_state.current_map_index].width
+        BlendMode::Alpha
     );
 
     // Draw different sizes of shadows based on player state
@@ -69,50 +199,49 @@ fn draw_player(game_state: &mut GameState) {
             &game_state.sprites.shadow[1]
     };
 
-    // Draw associated shadow if not on or above obstacle
+    // Queue associated shadow beneath the player if not on or above obstacle.
+    // Multiplied so the shadow darkens the grass instead of covering it
     if !game_state.player.on_obstacle && !game_state.player.above_obstacle {
-        draw_sprite(
-            game_state.player.x as usize,
-            GROUND as usize + 3,
+        render_queue.push_sprite(
+            Layer::Entities,
+            -1,
+            game_state.player.x as i32,
+            GROUND as i32 + 3,
             shadow_sprite,
-            game_state.window_buffer,
-            game_state.all_maps[game_state.current_map_index].width
+            BlendMode::Multiply
         );
-
     }
 }
 
-fn draw_game_world(game_state: &mut GameState) {
-
-    // First draw the blue background
-    draw_sprite(0, 0, &game_state.sprites.blue_background[0], game_state.window_buffer, game_state.all_maps[game_state.current_map_index].width);
-
-    // Then draw the grass, which alternates between two sprites to emulate wind
-    draw_sprite(0, game_state.all_maps[game_state.current_map_index].height - game_state.sprites.grass[0].height as usize, &game_state.sprites.grass[game_state.grass_sprite_index], game_state.window_buffer, game_state.all_maps[game_state.current_map_index].width);
-
-    // Then draw the sky, which alternates between four sprites to emulate clouds
-    draw_sprite(0, 0, &game_state.sprites.sky[game_state.sky_sprite_index], game_state.window_buffer, game_state.all_maps[game_state.current_map_index].width);
-
+fn queue_game_world<'s>(game_state: &'s GameState, render_queue: &mut RenderQueue<'s>) {
+    game_state.all_maps[game_state.current_map_index].obstacles.iter().for_each(|(_, obstacle)| {
+        let box_sprite = game_state.sprites.box_frame(obstacle.material.skin(), obstacle.material.damage(obstacle.durability));
 
+        let (x, y) = (obstacle.x_left as i32, obstacle.y_bottom as i32);
+        render_queue.push_sprite(Layer::World, 0, x, y, box_sprite, BlendMode::Alpha);
 
-    game_state.all_maps[game_state.current_map_index].obstacles.iter().enumerate().for_each(|(index, obstacle)| {
-        if obstacle.active {
-            let metal_box_sprite =
-            if obstacle.durability == 2 {
-                &game_state.sprites.metal_box[0] // undamaged
-            } else if obstacle.durability == 1 {
-                &game_state.sprites.metal_box[1] // slightly damaged
-            } else {
-                &game_state.sprites.metal_box[2] // damaged
-            };
-
-            draw_sprite(obstacle.x_left as usize, obstacle.y_bottom as usize, metal_box_sprite, game_state.window_buffer, game_state.all_maps[game_state.current_map_index].width);
+        // Lit boxes flash, faster as the fuse burns down. Screen keeps light boxes from blowing out to white
+        if let Some(fuse) = obstacle.fuse {
+            let flash_period = if fuse < FUSE_DURATION / 3 { 3 } else { 8 };
+            if (fuse / flash_period) % 2 == 0 {
+                render_queue.push_sprite(Layer::World, 1, x, y, box_sprite, BlendMode::Screen);
+            }
         }
-
     });
+}
+
+fn queue_effects(game_state: &GameState, render_queue: &mut RenderQueue) {
+    // Explosions grow from the centre of the box and fade out
+    for explosion in &game_state.explosions {
+        let progress = explosion.progress();
+        let alpha = ((1.0 - progress) * 255.0) as u32;
+        let radius = EXPLOSION_RADIUS * (0.3 + 0.7 * progress);
+        let center = (explosion.x as i32, explosion.y as i32);
 
+        let blast = Shape::Circle { center, radius: radius as i32, filled: true };
+        render_queue.push_shape(Layer::Effects, 0, blast, (alpha << 24) | EXPLOSION_OUTER_COLOR, BlendMode::Additive);
 
-    if game_state.player.game_over {
-        draw_sprite(0, 0, &game_state.sprites.game_over[game_state.game_over_index], game_state.window_buffer, game_state.all_maps[game_state.current_map_index].width);
+        let core = Shape::Circle { center, radius: (radius * 0.5) as i32, filled: true };
+        render_queue.push_shape(Layer::Effects, 1, core, (alpha << 24) | EXPLOSION_INNER_COLOR, BlendMode::Additive);
     }
-}
\ No newline at end of file
+}